@@ -0,0 +1,202 @@
+use std::ffi::{c_char, c_int, c_long, c_uint, c_ulong, c_void, CStr, CString};
+
+// libasound is loaded when a device is opened, so builds don't need its headers
+// and hosts without it only lose sound
+extern "C" {
+    fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlclose(handle: *mut c_void) -> c_int;
+}
+
+const RTLD_NOW: c_int = 2;
+const SND_PCM_STREAM_PLAYBACK: c_int = 0;
+const SND_PCM_NONBLOCK: c_int = 1;
+const SND_PCM_FORMAT_S16_LE: c_int = 2;
+const SND_PCM_ACCESS_RW_INTERLEAVED: c_int = 3;
+const EAGAIN: c_long = 11;
+// the device's own buffer, the emulator keeps much less than this queued
+const LATENCY_MICROSECONDS: c_uint = 250_000;
+
+type Pcm = *mut c_void;
+
+struct Library {
+    handle: *mut c_void,
+    open: unsafe extern "C" fn(*mut Pcm, *const c_char, c_int, c_int) -> c_int,
+    set_params: unsafe extern "C" fn(Pcm, c_int, c_int, c_uint, c_uint, c_int, c_uint) -> c_int,
+    writei: unsafe extern "C" fn(Pcm, *const c_void, c_ulong) -> c_long,
+    delay: unsafe extern "C" fn(Pcm, *mut c_long) -> c_int,
+    recover: unsafe extern "C" fn(Pcm, c_int, c_int) -> c_int,
+    nonblock: unsafe extern "C" fn(Pcm, c_int) -> c_int,
+    drain: unsafe extern "C" fn(Pcm) -> c_int,
+    close: unsafe extern "C" fn(Pcm) -> c_int,
+    strerror: unsafe extern "C" fn(c_int) -> *const c_char,
+}
+
+// an ALSA playback device taking mono 16 bit samples without blocking
+pub struct AlsaDevice {
+    library: Library,
+    pcm: Pcm,
+}
+
+// the handle is only used by the thread that owns the state it is part of
+unsafe impl Send for AlsaDevice {}
+
+// a symbol as the function pointer type of the field it fills
+unsafe fn function<F>(pointer: *mut c_void) -> F {
+    std::mem::transmute_copy(&pointer)
+}
+
+fn load_library() -> Result<Library, String> {
+    let handle = unsafe { dlopen(c"libasound.so.2".as_ptr(), RTLD_NOW) };
+    if handle.is_null() {
+        return Err("libasound.so.2 not found".to_string());
+    }
+    let symbol = |name: &str| {
+        let name = CString::new(name).unwrap();
+        match unsafe { dlsym(handle, name.as_ptr()) } {
+            pointer if pointer.is_null() => Err(format!("libasound has no {:?}", name)),
+            pointer => Ok(pointer),
+        }
+    };
+
+    let library = (|| unsafe {
+        Ok(Library {
+            handle,
+            open: function(symbol("snd_pcm_open")?),
+            set_params: function(symbol("snd_pcm_set_params")?),
+            writei: function(symbol("snd_pcm_writei")?),
+            delay: function(symbol("snd_pcm_delay")?),
+            recover: function(symbol("snd_pcm_recover")?),
+            nonblock: function(symbol("snd_pcm_nonblock")?),
+            drain: function(symbol("snd_pcm_drain")?),
+            close: function(symbol("snd_pcm_close")?),
+            strerror: function(symbol("snd_strerror")?),
+        })
+    })();
+    if library.is_err() {
+        unsafe { dlclose(handle) };
+    }
+
+    return library;
+}
+
+impl AlsaDevice {
+    // device is an ALSA name like "default" or "hw:0"
+    pub fn open(device: &str, sample_rate: u32) -> Result<AlsaDevice, String> {
+        let library = load_library()?;
+        let name = match CString::new(device) {
+            Ok(res) => res,
+            Err(_) => return Err(format!("invalid device name {}", device)),
+        };
+
+        let mut pcm = std::ptr::null_mut();
+        let result = unsafe {
+            (library.open)(
+                &mut pcm,
+                name.as_ptr(),
+                SND_PCM_STREAM_PLAYBACK,
+                SND_PCM_NONBLOCK,
+            )
+        };
+        let mut device = AlsaDevice { library, pcm };
+        if result < 0 {
+            return Err(format!(
+                "{}: {}",
+                name.to_string_lossy(),
+                device.error(result)
+            ));
+        }
+
+        let result = unsafe {
+            (device.library.set_params)(
+                device.pcm,
+                SND_PCM_FORMAT_S16_LE,
+                SND_PCM_ACCESS_RW_INTERLEAVED,
+                1,
+                sample_rate,
+                1,
+                LATENCY_MICROSECONDS,
+            )
+        };
+        if result < 0 {
+            let why = device.error(result);
+            unsafe { (device.library.close)(device.pcm) };
+            device.pcm = std::ptr::null_mut();
+            return Err(format!("{}: {}", name.to_string_lossy(), why));
+        }
+
+        Ok(device)
+    }
+
+    fn error(&self, code: c_int) -> String {
+        unsafe { CStr::from_ptr((self.library.strerror)(code)) }
+            .to_string_lossy()
+            .to_string()
+    }
+
+    // queue what fits in the device's buffer, restarting after an underrun
+    pub fn write(&mut self, samples: &[i16]) -> Result<(), String> {
+        let mut written = 0;
+        while written < samples.len() {
+            let result = unsafe {
+                (self.library.writei)(
+                    self.pcm,
+                    samples[written..].as_ptr() as *const c_void,
+                    (samples.len() - written) as c_ulong,
+                )
+            };
+            if result == -EAGAIN {
+                // full, the rest is dropped rather than blocking emulation
+                return Ok(());
+            }
+            if result < 0 {
+                let recovered = unsafe { (self.library.recover)(self.pcm, result as c_int, 1) };
+                if recovered < 0 {
+                    return Err(self.error(recovered));
+                }
+                continue;
+            }
+            written += result as usize;
+        }
+
+        Ok(())
+    }
+
+    // frames written but not yet heard, as the device reports it
+    pub fn queued(&mut self) -> usize {
+        let mut delay: c_long = 0;
+        let result = unsafe { (self.library.delay)(self.pcm, &mut delay) };
+        if result < 0 {
+            unsafe { (self.library.recover)(self.pcm, result, 1) };
+            return 0;
+        }
+
+        return delay.max(0) as usize;
+    }
+
+    // play out what is queued and close the device
+    pub fn finish(&mut self) {
+        if self.pcm.is_null() {
+            return;
+        }
+        unsafe {
+            (self.library.nonblock)(self.pcm, 0);
+            (self.library.drain)(self.pcm);
+            (self.library.close)(self.pcm);
+        }
+        self.pcm = std::ptr::null_mut();
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        unsafe { dlclose(self.handle) };
+    }
+}
+
+// every way out of the emulator drops the device, playing out and closing it
+impl Drop for AlsaDevice {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
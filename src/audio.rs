@@ -0,0 +1,89 @@
+#[cfg(target_os = "linux")]
+use crate::alsa::AlsaDevice;
+use crate::mixer::SAMPLE_RATE;
+use crate::wav::Wave;
+
+pub enum AudioSink {
+    #[cfg(target_os = "linux")]
+    Device(AlsaDevice),
+    File {
+        filename: String,
+        samples: Vec<i16>,
+    },
+    Null,
+}
+
+impl AudioSink {
+    // an ALSA device name, "default" is the host's default output
+    #[cfg(target_os = "linux")]
+    pub fn open_device(device: &str) -> Result<AudioSink, String> {
+        return Ok(AudioSink::Device(AlsaDevice::open(device, SAMPLE_RATE)?));
+    }
+
+    // only ALSA is supported, elsewhere sound is silent
+    #[cfg(not(target_os = "linux"))]
+    pub fn open_device(_device: &str) -> Result<AudioSink, String> {
+        return Ok(AudioSink::Null);
+    }
+}
+
+// takes PCM from the sound board and reports how far it runs ahead of playback
+pub struct AudioOutput {
+    pub sink: AudioSink,
+    // 0.0-1.0
+    volume: f64,
+}
+
+impl AudioOutput {
+    pub fn new(sink: AudioSink, volume: f64) -> AudioOutput {
+        AudioOutput { sink, volume }
+    }
+
+    pub fn queue(&mut self, samples: Vec<i16>) {
+        let samples: Vec<i16> = samples
+            .iter()
+            .map(|sample| (*sample as f64 * self.volume) as i16)
+            .collect();
+
+        match &mut self.sink {
+            #[cfg(target_os = "linux")]
+            AudioSink::Device(device) => {
+                if let Err(why) = device.write(&samples) {
+                    println!("Audio device failed ({}), sound disabled", why);
+                    device.finish();
+                    self.sink = AudioSink::Null;
+                }
+            }
+            AudioSink::File {
+                samples: recorded, ..
+            } => recorded.extend_from_slice(&samples),
+            AudioSink::Null => (),
+        }
+    }
+
+    // seconds of audio queued but not yet played, only known for a device
+    pub fn buffered(&mut self) -> Option<f64> {
+        match &mut self.sink {
+            #[cfg(target_os = "linux")]
+            AudioSink::Device(device) => Some(device.queued() as f64 / SAMPLE_RATE as f64),
+            _ => None,
+        }
+    }
+
+    pub fn finish(&mut self) {
+        match &mut self.sink {
+            #[cfg(target_os = "linux")]
+            AudioSink::Device(device) => device.finish(),
+            AudioSink::File { filename, samples } => {
+                let wave = Wave {
+                    sample_rate: SAMPLE_RATE,
+                    samples: std::mem::take(samples),
+                };
+                if let Err(why) = wave.write(filename) {
+                    println!("Failed to write sound: {}", why);
+                }
+            }
+            AudioSink::Null => (),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use crate::disassemble::disassemble8080_op_text;
+
+// deepest the shadow stack may grow before the oldest frames are forgotten
+const MAX_DEPTH: usize = 256;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrameKind {
+    Call,
+    Restart,
+    Interrupt,
+}
+
+#[derive(Clone)]
+pub struct Frame {
+    pub function: u16,
+    pub call_site: u16,
+    pub return_address: u16,
+    // where the return address lives in memory
+    pub slot: u16,
+    pub kind: FrameKind,
+}
+
+pub fn frame_name(function: u16, kind: FrameKind) -> String {
+    match kind {
+        FrameKind::Interrupt => format!("int_{:04x}", function),
+        _ => format!("sub_{:04x}", function),
+    }
+}
+
+impl Frame {
+    pub fn name(&self) -> String {
+        frame_name(self.function, self.kind)
+    }
+}
+
+// call stack rebuilt from observed CALL/RST/RET rather than raw stack memory
+#[derive(Clone)]
+pub struct ShadowStack {
+    pub frames: Vec<Frame>,
+}
+
+impl ShadowStack {
+    pub fn new() -> ShadowStack {
+        ShadowStack { frames: Vec::new() }
+    }
+
+    // the stack grows down so frames stored below SP have already been discarded
+    pub fn unwind(&mut self, stack_pointer: u16) {
+        while let Some(frame) = self.frames.last() {
+            if frame.slot >= stack_pointer {
+                break;
+            }
+            self.frames.pop();
+        }
+    }
+
+    // called after the return address was pushed
+    pub fn push(&mut self, frame: Frame) {
+        self.unwind(frame.slot);
+        if self.frames.len() == MAX_DEPTH {
+            self.frames.remove(0);
+        }
+        self.frames.push(frame);
+    }
+
+    // called before the return address is popped
+    pub fn pop(&mut self, stack_pointer: u16) {
+        self.unwind(stack_pointer);
+        // a RET through an address pushed by hand is a jump, not a return
+        if self
+            .frames
+            .last()
+            .is_some_and(|frame| frame.slot == stack_pointer)
+        {
+            self.frames.pop();
+        }
+    }
+
+    // XTHL swaps the return address for HL
+    pub fn rewrite(&mut self, stack_pointer: u16, value: u16) {
+        if let Some(frame) = self
+            .frames
+            .iter_mut()
+            .rev()
+            .find(|frame| frame.slot == stack_pointer)
+        {
+            frame.return_address = value;
+        }
+    }
+
+    // interrupts have no calling instruction, only the address they interrupted
+    pub fn mark_interrupt(&mut self) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.call_site = frame.return_address;
+            frame.kind = FrameKind::Interrupt;
+        }
+    }
+
+    // symbol for an address inside the given frame's function
+    fn symbol(frame: Option<&Frame>, address: u16) -> String {
+        match frame {
+            Some(frame) => format!(
+                "{}+{:04x}",
+                frame.name(),
+                address.wrapping_sub(frame.function)
+            ),
+            None => format!("root+{:04x}", address),
+        }
+    }
+
+    // innermost frame first, each line showing where that function was entered from
+    pub fn print_backtrace(&self, memory: &[u8], program_counter: u16) {
+        let (text, _) = disassemble8080_op_text(memory, program_counter as usize);
+        println!("Backtrace:");
+        println!(
+            "#0  {:04x}  {:<18} {}",
+            program_counter,
+            ShadowStack::symbol(self.frames.last(), program_counter),
+            text
+        );
+
+        for (depth, ind) in (0..self.frames.len()).rev().enumerate() {
+            let frame = &self.frames[ind];
+            let caller = ind.checked_sub(1).map(|caller| &self.frames[caller]);
+            let symbol = ShadowStack::symbol(caller, frame.call_site);
+            match frame.kind {
+                FrameKind::Interrupt => println!(
+                    "#{:<2} {:04x}  {:<18} <interrupt RST {}>",
+                    depth + 1,
+                    frame.call_site,
+                    symbol,
+                    frame.function / 8
+                ),
+                _ => {
+                    let (text, _) = disassemble8080_op_text(memory, frame.call_site as usize);
+                    println!(
+                        "#{:<2} {:04x}  {:<18} {}",
+                        depth + 1,
+                        frame.call_site,
+                        symbol,
+                        text
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,466 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+// the TOML values settings use
+enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+struct Entry {
+    // per game sections are [game.<name>] and [game.<name>.<table>]
+    game: Option<String>,
+    table: String,
+    key: String,
+    value: Value,
+    line: usize,
+}
+
+// settings from a TOML file, applied as the flags they stand for. The subset read is tables,
+// bare keys, basic and literal strings, integers, floats, booleans and arrays, which may
+// run over several lines; no inline tables, dotted keys, dates or multi-line strings
+pub struct Config {
+    filename: String,
+    entries: Vec<Entry>,
+}
+
+// a "basic" string with escapes or a 'literal' one without, from its opening quote
+fn parse_string(text: &str) -> Result<(String, &str), String> {
+    let quote = text.chars().next().unwrap();
+    let mut value = String::new();
+    let mut chars = text[1..].char_indices();
+    while let Some((ind, character)) = chars.next() {
+        match character {
+            _ if character == quote => return Ok((value, &text[ind + 2..])),
+            '\\' if quote == '"' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, '"')) => value.push('"'),
+                Some((_, '\\')) => value.push('\\'),
+                _ => return Err("invalid escape in string".to_string()),
+            },
+            _ => value.push(character),
+        }
+    }
+
+    return Err("unterminated string".to_string());
+}
+
+// a value at the start of the text and what follows it
+fn parse_value(text: &str) -> Result<(Value, &str), String> {
+    let text = text.trim_start();
+    if text.starts_with(['"', '\'']) {
+        let (value, rest) = parse_string(text)?;
+        return Ok((Value::String(value), rest));
+    }
+    if let Some(mut rest) = text.strip_prefix('[') {
+        let mut values = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(values), after));
+            }
+            let (value, after) = parse_value(rest)?;
+            values.push(value);
+            rest = after.trim_start();
+            match rest.strip_prefix(',') {
+                Some(after) => rest = after,
+                None if rest.starts_with(']') => (),
+                None => return Err("expected , or ] in array".to_string()),
+            }
+        }
+    }
+
+    let end = text.find([',', ']']).unwrap_or(text.len());
+    let (word, rest) = (text[..end].trim_end(), &text[end..]);
+    let number = word.replace('_', "");
+    let value = match word {
+        "true" => Value::Boolean(true),
+        "false" => Value::Boolean(false),
+        _ => {
+            if let Some(hex) = number.strip_prefix("0x") {
+                match i64::from_str_radix(hex, 16) {
+                    Ok(res) => Value::Integer(res),
+                    Err(_) => return Err(format!("invalid value {}", word)),
+                }
+            } else if let Ok(res) = number.parse() {
+                Value::Integer(res)
+            } else if let Ok(res) = number.parse() {
+                Value::Float(res)
+            } else {
+                return Err(format!("invalid value {} (strings need quotes)", word));
+            }
+        }
+    };
+
+    return Ok((value, rest));
+}
+
+// walks a line outside of strings, returning where a comment starts and how many
+// more arrays it opens than it closes
+fn scan_line(line: &str) -> (usize, i32) {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut depth = 0;
+    for (ind, character) in line.char_indices() {
+        match (quote, character) {
+            (Some('"'), '\\') => {
+                escaped = !escaped;
+                continue;
+            }
+            (Some(open), _) if character == open && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(character),
+            (None, '#') => return (ind, depth),
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            _ => (),
+        }
+        escaped = false;
+    }
+
+    return (line.len(), depth);
+}
+
+// strip a comment, leaving any # inside strings
+fn strip_comment(line: &str) -> &str {
+    return &line[..scan_line(line).0];
+}
+
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || "_-".contains(character))
+}
+
+impl Value {
+    fn as_string(&self) -> Option<String> {
+        match self {
+            Value::String(value) => Some(value.clone()),
+            Value::Integer(value) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl Config {
+    // $XDG_CONFIG_HOME/i8080_emulator/config.toml, or under ~/.config
+    pub fn default_path() -> Option<PathBuf> {
+        let directory = match env::var("XDG_CONFIG_HOME") {
+            Ok(res) if !res.is_empty() => PathBuf::from(res),
+            _ => PathBuf::from(env::var("HOME").ok()?).join(".config"),
+        };
+
+        return Some(directory.join("i8080_emulator").join("config.toml"));
+    }
+
+    pub fn load(filename: &str) -> Result<Config, String> {
+        let contents = match fs::read_to_string(filename) {
+            Ok(res) => res,
+            Err(why) => return Err(format!("{}: {}", filename, why)),
+        };
+
+        return Config::parse(filename, &contents);
+    }
+
+    fn parse(filename: &str, contents: &str) -> Result<Config, String> {
+        let mut entries = Vec::new();
+        let mut game = None;
+        let mut table = String::new();
+        let mut lines = contents.lines().enumerate();
+        while let Some((ind, line)) = lines.next() {
+            let error = |why: String| format!("{}:{}: {}", filename, ind + 1, why);
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[') {
+                let Some(header) = header.strip_suffix(']') else {
+                    return Err(error("expected ] after table name".to_string()));
+                };
+                let path: Vec<&str> = header.split('.').map(|part| part.trim()).collect();
+                if !path.iter().all(|part| is_bare_key(part)) {
+                    return Err(error(format!("invalid table name {}", header)));
+                }
+                (game, table) = match path[..] {
+                    ["game", name] => (Some(name.to_string()), String::new()),
+                    ["game", name, table] => (Some(name.to_string()), table.to_string()),
+                    [table] => (None, table.to_string()),
+                    _ => return Err(error(format!("invalid table name {}", header))),
+                };
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error("expected key = value".to_string()));
+            };
+            let key = key.trim();
+            if !is_bare_key(key) {
+                return Err(error(format!("invalid key {}", key)));
+            }
+            // an array runs on until its brackets close
+            let mut value = value.to_string();
+            while scan_line(&value).1 > 0 {
+                let Some((_, next)) = lines.next() else {
+                    return Err(error("unterminated array".to_string()));
+                };
+                value.push(' ');
+                value.push_str(strip_comment(next).trim());
+            }
+            let (value, rest) = parse_value(&value).map_err(error)?;
+            if !rest.trim().is_empty() {
+                return Err(error(format!("unexpected {} after value", rest.trim())));
+            }
+            entries.push(Entry {
+                game: game.clone(),
+                table: table.clone(),
+                key: key.to_string(),
+                value,
+                line: ind + 1,
+            });
+        }
+
+        Ok(Config {
+            filename: filename.to_string(),
+            entries,
+        })
+    }
+
+    // the machine the top level settings choose, for picking per game sections
+    pub fn machine(&self) -> Option<String> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|entry| {
+                entry.game.is_none() && entry.table.is_empty() && entry.key == "machine"
+            })
+            .find_map(|entry| entry.value.as_string())
+    }
+
+    // flags for the top level settings and then any section named for the game,
+    // placed before the command line so its flags win
+    pub fn to_args(&self, games: &[String]) -> Result<Vec<String>, String> {
+        let mut args = Vec::new();
+        let defaults = self.entries.iter().filter(|entry| entry.game.is_none());
+        let overrides: Vec<&Entry> = self
+            .entries
+            .iter()
+            .filter(|entry| matches!(&entry.game, Some(game) if games.contains(game)))
+            .collect();
+        // a setting in the game's section replaces the top level one rather than adding to it
+        let defaults = defaults.filter(|entry| {
+            !overrides
+                .iter()
+                .any(|game| game.table == entry.table && game.key == entry.key)
+        });
+        for entry in defaults.chain(overrides.iter().copied()) {
+            if let Err(why) = entry_args(entry, &mut args) {
+                return Err(format!("{}:{}: {}", self.filename, entry.line, why));
+            }
+        }
+
+        Ok(args)
+    }
+}
+
+fn entry_args(entry: &Entry, args: &mut Vec<String>) -> Result<(), String> {
+    let name = match entry.table.as_str() {
+        "" => entry.key.clone(),
+        table => format!("{}.{}", table, entry.key),
+    };
+    let string = || {
+        entry
+            .value
+            .as_string()
+            .ok_or_else(|| format!("{} must be a string", name))
+    };
+    let integer = || match entry.value {
+        Value::Integer(value) => Ok(value.to_string()),
+        _ => Err(format!("{} must be an integer", name)),
+    };
+    let number = || match entry.value {
+        Value::Integer(value) => Ok(value.to_string()),
+        Value::Float(value) => Ok(value.to_string()),
+        _ => Err(format!("{} must be a number", name)),
+    };
+
+    let (flag, value) = match (entry.table.as_str(), entry.key.as_str()) {
+        ("", "machine") => ("--machine", string()?),
+        ("", "clock") => ("--clock", number()?),
+        ("", "title") => ("--title", string()?),
+        ("dip", key) => ("--dip", format!("{}={}", key, string()?)),
+        // one key or a list of them
+        ("keys", key) => match &entry.value {
+            Value::Array(values) => {
+                let keys: Option<Vec<String>> = values.iter().map(Value::as_string).collect();
+                match keys {
+                    Some(keys) => ("--bind", format!("{}={}", key, keys.join(","))),
+                    None => return Err(format!("{} must be a key or a list of keys", name)),
+                }
+            }
+            _ => ("--bind", format!("{}={}", key, string()?)),
+        },
+        ("display", "scale") => ("--scale", integer()?),
+        ("display", "overlay") => ("--overlay", string()?),
+        ("display", "overlay_file") => ("--overlay-file", string()?),
+        ("display", "phosphor") => ("--phosphor", string()?),
+        ("display", "background") => ("--background", string()?),
+        ("display", "cocktail") => match entry.value {
+            Value::Boolean(cocktail) => {
+                let flag = match cocktail {
+                    true => "--cocktail",
+                    false => "--no-cocktail",
+                };
+                args.push(flag.to_string());
+                return Ok(());
+            }
+            _ => return Err(format!("{} must be true or false", name)),
+        },
+        // patches apply in the listed order
+        ("paths", "patches") => match &entry.value {
+            Value::Array(values) => {
+                for value in values {
+                    let Value::String(patch) = value else {
+                        return Err(format!("{} must be a list of strings", name));
+                    };
+                    args.push("--patch".to_string());
+                    args.push(patch.clone());
+                }
+                return Ok(());
+            }
+            _ => return Err(format!("{} must be a list of strings", name)),
+        },
+        ("paths", "saves") => ("--nvram", string()?),
+        ("paths", "rom_db") => ("--rom-db", string()?),
+        ("paths", "screenshot") => ("--screenshot", string()?),
+        _ => return Err(format!("unknown setting {}", name)),
+    };
+    args.push(flag.to_string());
+    args.push(value);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SETTINGS: &str = r#"
+machine = "invaders"   # the board
+clock = 2_000_000
+
+[keys]
+coin = ["c", "F6"]
+start = '1'
+
+[display]
+scale = 3
+cocktail = true
+
+[game.invadpt2]
+clock = 1.5e6
+
+[game.invadpt2.dip]
+lives = "4"
+"#;
+
+    #[test]
+    fn settings_to_args() {
+        let config = Config::parse("test.toml", SETTINGS).unwrap();
+        assert_eq!(config.machine(), Some("invaders".to_string()));
+        assert_eq!(
+            config.to_args(&[]).unwrap(),
+            [
+                "--machine",
+                "invaders",
+                "--clock",
+                "2000000",
+                "--bind",
+                "coin=c,F6",
+                "--bind",
+                "start=1",
+                "--scale",
+                "3",
+                "--cocktail"
+            ]
+        );
+        // the game's clock replaces the top level one
+        assert_eq!(
+            config.to_args(&["invadpt2".to_string()]).unwrap(),
+            [
+                "--machine",
+                "invaders",
+                "--bind",
+                "coin=c,F6",
+                "--bind",
+                "start=1",
+                "--scale",
+                "3",
+                "--cocktail",
+                "--clock",
+                "1500000",
+                "--dip",
+                "lives=4"
+            ]
+        );
+    }
+
+    #[test]
+    fn values() {
+        let (value, rest) = parse_value(r#""a \"quoted\" # string" # comment"#).unwrap();
+        assert!(matches!(value, Value::String(text) if text == r#"a "quoted" # string"#));
+        assert_eq!(rest, " # comment");
+        assert!(matches!(parse_value("0x1f").unwrap().0, Value::Integer(31)));
+        assert!(matches!(
+            parse_value("false").unwrap().0,
+            Value::Boolean(false)
+        ));
+        assert!(matches!(
+            parse_value("[1, [2], ]").unwrap().0,
+            Value::Array(values) if values.len() == 2
+        ));
+        assert_eq!(strip_comment(r##"key = "#" # comment"##), r##"key = "#" "##);
+    }
+
+    #[test]
+    fn arrays_over_several_lines() {
+        let config = Config::parse(
+            "test.toml",
+            "[keys]\nfire = [\n  \"space\", # main\n  \"z\"\n]\n",
+        )
+        .unwrap();
+        assert_eq!(config.to_args(&[]).unwrap(), ["--bind", "fire=space,z"]);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = |contents: &str| Config::parse("test.toml", contents).err().unwrap();
+        assert_eq!(
+            error("machine = invaders"),
+            "test.toml:1: invalid value invaders (strings need quotes)"
+        );
+        assert_eq!(
+            error("\ntitle = \"open"),
+            "test.toml:2: unterminated string"
+        );
+        assert_eq!(
+            error("[keys]\nfire = [\"z\""),
+            "test.toml:2: unterminated array"
+        );
+        assert_eq!(
+            error("[game.a.b.c]"),
+            "test.toml:1: invalid table name game.a.b.c"
+        );
+
+        let config = Config::parse("test.toml", "\n[display]\nscale = \"big\"").unwrap();
+        assert_eq!(
+            config.to_args(&[]).err().unwrap(),
+            "test.toml:3: display.scale must be an integer"
+        );
+    }
+}
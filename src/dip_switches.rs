@@ -0,0 +1,97 @@
+use std::fs;
+
+use crate::machine::DipSetting;
+
+// the machine's DIP settings and which option each is set to
+#[derive(Clone)]
+pub struct DipSwitches {
+    settings: Vec<DipSetting>,
+    selected: Vec<usize>,
+}
+
+impl DipSwitches {
+    pub fn new(settings: Vec<DipSetting>) -> DipSwitches {
+        DipSwitches {
+            selected: vec![0; settings.len()],
+            settings,
+        }
+    }
+
+    // replace the switch bits of an input port value, keeping the controls
+    pub fn apply(&self, port: usize, value: u8) -> u8 {
+        let mut value = value;
+        for (ind, setting) in self.settings.iter().enumerate() {
+            if setting.port == port {
+                value = (value & !setting.mask) | setting.options[self.selected[ind]].1;
+            }
+        }
+
+        return value;
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let Some(ind) = self
+            .settings
+            .iter()
+            .position(|setting| setting.name == name)
+        else {
+            return Err(format!("unknown DIP switch {}", name));
+        };
+        let options = &self.settings[ind].options;
+        match options.iter().position(|(option, _)| *option == value) {
+            Some(option) => self.selected[ind] = option,
+            None => {
+                let names: Vec<&str> = options.iter().map(|(option, _)| *option).collect();
+                return Err(format!(
+                    "{} must be one of {}, got {}",
+                    name,
+                    names.join(", "),
+                    value
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // step a setting to its next option, for the function keys
+    pub fn cycle(&mut self, ind: usize) {
+        if ind < self.settings.len() {
+            self.selected[ind] = (self.selected[ind] + 1) % self.settings[ind].options.len();
+        }
+    }
+
+    // "name = value" per line, # starts a comment
+    pub fn load(&mut self, filename: &str) -> Result<(), String> {
+        let contents = match fs::read_to_string(filename) {
+            Ok(res) => res,
+            Err(why) => return Err(format!("{}: {}", filename, why)),
+        };
+
+        for (ind, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let result = match line.split_once('=') {
+                Some((name, value)) => self.set(name.trim(), value.trim()),
+                None => Err("expected name = value".to_string()),
+            };
+            if let Err(why) = result {
+                return Err(format!("{}:{}: {}", filename, ind + 1, why));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn print(&self) {
+        let values: Vec<String> = self
+            .settings
+            .iter()
+            .zip(self.selected.iter())
+            .map(|(setting, selected)| format!("{} {}", setting.name, setting.options[*selected].0))
+            .collect();
+        println!("DIP switches: {}", values.join(", "));
+    }
+}
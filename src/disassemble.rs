@@ -1,492 +1,406 @@
-// convert codes to names and print it out
-pub fn disassemble8080_op(code_buffer: &[u8], program_counter: usize) -> usize {
+use std::fmt::Write;
+
+use crate::patch;
+
+// convert codes to names and print it out, marking patched instructions
+pub fn disassemble8080_op(code_buffer: &[u8], program_counter: usize, patched: &[bool]) -> usize {
+    let (text, op_bytes) = disassemble8080_op_text(code_buffer, program_counter);
+    let note = patch::note(patched, program_counter, op_bytes);
+    println!("{:04x} {}{}", program_counter, text, note);
+
+    return op_bytes;
+}
+
+// convert codes to names, returning the text and the instruction length
+pub fn disassemble8080_op_text(code_buffer: &[u8], program_counter: usize) -> (String, usize) {
+    let mut text = String::new();
     let mut op_bytes = 1;
-    print!("{:04x} ", program_counter);
-    match code_buffer[program_counter] {
+    // operands past the end of the buffer read as 0
+    let byte = |offset: usize| {
+        code_buffer
+            .get(program_counter + offset)
+            .copied()
+            .unwrap_or(0)
+    };
+    match byte(0) {
         0x00 | 0x08 | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 | 0xcb | 0xd9 | 0xdd | 0xed
-        | 0xfd => print!("NOP"),
+        | 0xfd => write!(text, "NOP").unwrap(),
         0x01 => {
-            print!(
-                "LXI    B,#${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "LXI    B,#${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
-        0x02 => print!("STAX   B"),
-        0x03 => print!("INX    B"),
-        0x04 => print!("INR    B"),
-        0x05 => print!("DCR    B"),
+        0x02 => write!(text, "STAX   B").unwrap(),
+        0x03 => write!(text, "INX    B").unwrap(),
+        0x04 => write!(text, "INR    B").unwrap(),
+        0x05 => write!(text, "DCR    B").unwrap(),
         0x06 => {
-            print!("MVI    B,#${:02x}", code_buffer[program_counter + 1]);
+            write!(text, "MVI    B,#${:02x}", byte(1)).unwrap();
             op_bytes = 2
         }
-        0x07 => print!("RLC"),
-        0x09 => print!("DAD    B"),
-        0x0a => print!("LDAX   B"),
-        0x0b => print!("DCX    B"),
-        0x0c => print!("INR    C"),
-        0x0d => print!("DCR    C"),
+        0x07 => write!(text, "RLC").unwrap(),
+        0x09 => write!(text, "DAD    B").unwrap(),
+        0x0a => write!(text, "LDAX   B").unwrap(),
+        0x0b => write!(text, "DCX    B").unwrap(),
+        0x0c => write!(text, "INR    C").unwrap(),
+        0x0d => write!(text, "DCR    C").unwrap(),
         0x0e => {
-            print!("MVI    C,#${:02x}", code_buffer[program_counter + 1]);
+            write!(text, "MVI    C,#${:02x}", byte(1)).unwrap();
             op_bytes = 2
         }
-        0x0f => print!("RRC"),
+        0x0f => write!(text, "RRC").unwrap(),
         0x11 => {
-            print!(
-                "LXI    D,#${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "LXI    D,#${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
-        0x12 => print!("STAX   D"),
-        0x13 => print!("INX    D"),
-        0x14 => print!("INR    D"),
-        0x15 => print!("DCR    D"),
+        0x12 => write!(text, "STAX   D").unwrap(),
+        0x13 => write!(text, "INX    D").unwrap(),
+        0x14 => write!(text, "INR    D").unwrap(),
+        0x15 => write!(text, "DCR    D").unwrap(),
         0x16 => {
-            print!("MVI    D,#${:02x}", code_buffer[program_counter + 1]);
+            write!(text, "MVI    D,#${:02x}", byte(1)).unwrap();
             op_bytes = 2
         }
-        0x17 => print!("RAL"),
-        0x19 => print!("DAD    D"),
-        0x1a => print!("LDAX   D"),
-        0x1b => print!("DCX    D"),
-        0x1c => print!("INR    E"),
-        0x1d => print!("DCR    E"),
+        0x17 => write!(text, "RAL").unwrap(),
+        0x19 => write!(text, "DAD    D").unwrap(),
+        0x1a => write!(text, "LDAX   D").unwrap(),
+        0x1b => write!(text, "DCX    D").unwrap(),
+        0x1c => write!(text, "INR    E").unwrap(),
+        0x1d => write!(text, "DCR    E").unwrap(),
         0x1e => {
-            print!("MVI    E,#${:02x}", code_buffer[program_counter + 1]);
+            write!(text, "MVI    E,#${:02x}", byte(1)).unwrap();
             op_bytes = 2
         }
-        0x1f => print!("RAR"),
+        0x1f => write!(text, "RAR").unwrap(),
         0x21 => {
-            print!(
-                "LXI    H,#${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "LXI    H,#${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
         0x22 => {
-            print!(
-                "SHLD   ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "SHLD   ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
-        0x23 => print!("INX    H"),
-        0x24 => print!("INR    H"),
-        0x25 => print!("DCR    H"),
+        0x23 => write!(text, "INX    H").unwrap(),
+        0x24 => write!(text, "INR    H").unwrap(),
+        0x25 => write!(text, "DCR    H").unwrap(),
         0x26 => {
-            print!("MVI    H,#${:02x}", code_buffer[program_counter + 1]);
+            write!(text, "MVI    H,#${:02x}", byte(1)).unwrap();
             op_bytes = 2
         }
-        0x27 => print!("DAA"),
-        0x29 => print!("DAD    H"),
+        0x27 => write!(text, "DAA").unwrap(),
+        0x29 => write!(text, "DAD    H").unwrap(),
         0x2a => {
-            print!(
-                "LHLD   ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "LHLD   ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
-        0x2b => print!("DCX    H"),
-        0x2c => print!("INR    L"),
-        0x2d => print!("DCR    L"),
+        0x2b => write!(text, "DCX    H").unwrap(),
+        0x2c => write!(text, "INR    L").unwrap(),
+        0x2d => write!(text, "DCR    L").unwrap(),
         0x2e => {
-            print!("MVI    L,#${:02x}", code_buffer[program_counter + 1]);
+            write!(text, "MVI    L,#${:02x}", byte(1)).unwrap();
             op_bytes = 2
         }
-        0x2f => print!("CMA"),
+        0x2f => write!(text, "CMA").unwrap(),
         0x31 => {
-            print!(
-                "LXI    SP,#${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "LXI    SP,#${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
         0x32 => {
-            print!(
-                "STA    ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "STA    ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
-        0x33 => print!("INX    SP"),
-        0x34 => print!("INR    M"),
-        0x35 => print!("DCR    M"),
+        0x33 => write!(text, "INX    SP").unwrap(),
+        0x34 => write!(text, "INR    M").unwrap(),
+        0x35 => write!(text, "DCR    M").unwrap(),
         0x36 => {
-            print!("MVI    M,#${:02x}", code_buffer[program_counter + 1]);
+            write!(text, "MVI    M,#${:02x}", byte(1)).unwrap();
             op_bytes = 2
         }
-        0x37 => print!("STC"),
-        0x39 => print!("DAD    SP"),
+        0x37 => write!(text, "STC").unwrap(),
+        0x39 => write!(text, "DAD    SP").unwrap(),
         0x3a => {
-            print!(
-                "LDA    ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "LDA    ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
-        0x3b => print!("DCX    SP"),
-        0x3c => print!("INR    A"),
-        0x3d => print!("DCR    A"),
+        0x3b => write!(text, "DCX    SP").unwrap(),
+        0x3c => write!(text, "INR    A").unwrap(),
+        0x3d => write!(text, "DCR    A").unwrap(),
         0x3e => {
-            print!("MVI    A,#${:02x}", code_buffer[program_counter + 1]);
+            write!(text, "MVI    A,#${:02x}", byte(1)).unwrap();
             op_bytes = 2
         }
-        0x3f => print!("CMC"),
-        0x40 => print!("MOV    B,B"),
-        0x41 => print!("MOV    B,C"),
-        0x42 => print!("MOV    B,D"),
-        0x43 => print!("MOV    B,E"),
-        0x44 => print!("MOV    B,H"),
-        0x45 => print!("MOV    B,L"),
-        0x46 => print!("MOV    B,M"),
-        0x47 => print!("MOV    B,A"),
-        0x48 => print!("MOV    C,B"),
-        0x49 => print!("MOV    C,C"),
-        0x4a => print!("MOV    C,D"),
-        0x4b => print!("MOV    C,E"),
-        0x4c => print!("MOV    C,H"),
-        0x4d => print!("MOV    C,L"),
-        0x4e => print!("MOV    C,M"),
-        0x4f => print!("MOV    C,A"),
-        0x50 => print!("MOV    D,B"),
-        0x51 => print!("MOV    D,C"),
-        0x52 => print!("MOV    D,D"),
-        0x53 => print!("MOV    D,E"),
-        0x54 => print!("MOV    D,H"),
-        0x55 => print!("MOV    D,L"),
-        0x56 => print!("MOV    D,M"),
-        0x57 => print!("MOV    D,A"),
-        0x58 => print!("MOV    E,B"),
-        0x59 => print!("MOV    E,C"),
-        0x5a => print!("MOV    E,D"),
-        0x5b => print!("MOV    E,E"),
-        0x5c => print!("MOV    E,H"),
-        0x5d => print!("MOV    E,L"),
-        0x5e => print!("MOV    E,M"),
-        0x5f => print!("MOV    E,A"),
-        0x60 => print!("MOV    H,B"),
-        0x61 => print!("MOV    H,C"),
-        0x62 => print!("MOV    H,D"),
-        0x63 => print!("MOV    H,E"),
-        0x64 => print!("MOV    H,H"),
-        0x65 => print!("MOV    H,L"),
-        0x66 => print!("MOV    H,M"),
-        0x67 => print!("MOV    H,A"),
-        0x68 => print!("MOV    L,B"),
-        0x69 => print!("MOV    L,C"),
-        0x6a => print!("MOV    L,D"),
-        0x6b => print!("MOV    L,E"),
-        0x6c => print!("MOV    L,H"),
-        0x6d => print!("MOV    L,L"),
-        0x6e => print!("MOV    L,M"),
-        0x6f => print!("MOV    L,A"),
-        0x70 => print!("MOV    M,B"),
-        0x71 => print!("MOV    M,C"),
-        0x72 => print!("MOV    M,D"),
-        0x73 => print!("MOV    M,E"),
-        0x74 => print!("MOV    M,H"),
-        0x75 => print!("MOV    M,L"),
-        0x76 => print!("HLT"),
-        0x77 => print!("MOV    M,A"),
-        0x78 => print!("MOV    A,B"),
-        0x79 => print!("MOV    A,C"),
-        0x7a => print!("MOV    A,D"),
-        0x7b => print!("MOV    A,E"),
-        0x7c => print!("MOV    A,H"),
-        0x7d => print!("MOV    A,L"),
-        0x7e => print!("MOV    A,M"),
-        0x7f => print!("MOV    A,A"),
-        0x80 => print!("ADD    B"),
-        0x81 => print!("ADD    C"),
-        0x82 => print!("ADD    D"),
-        0x83 => print!("ADD    E"),
-        0x84 => print!("ADD    H"),
-        0x85 => print!("ADD    L"),
-        0x86 => print!("ADD    M"),
-        0x87 => print!("ADD    A"),
-        0x88 => print!("ADC    B"),
-        0x89 => print!("ADC    C"),
-        0x8a => print!("ADC    D"),
-        0x8b => print!("ADC    E"),
-        0x8c => print!("ADC    H"),
-        0x8d => print!("ADC    L"),
-        0x8e => print!("ADC    M"),
-        0x8f => print!("ADC    A"),
-        0x90 => print!("SUB    B"),
-        0x91 => print!("SUB    C"),
-        0x92 => print!("SUB    D"),
-        0x93 => print!("SUB    E"),
-        0x94 => print!("SUB    H"),
-        0x95 => print!("SUB    L"),
-        0x96 => print!("SUB    M"),
-        0x97 => print!("SUB    A"),
-        0x98 => print!("SBB    B"),
-        0x99 => print!("SBB    C"),
-        0x9a => print!("SBB    D"),
-        0x9b => print!("SBB    E"),
-        0x9c => print!("SBB    H"),
-        0x9d => print!("SBB    L"),
-        0x9e => print!("SBB    M"),
-        0x9f => print!("SBB    A"),
-        0xa0 => print!("ANA    B"),
-        0xa1 => print!("ANA    C"),
-        0xa2 => print!("ANA    D"),
-        0xa3 => print!("ANA    E"),
-        0xa4 => print!("ANA    H"),
-        0xa5 => print!("ANA    L"),
-        0xa6 => print!("ANA    M"),
-        0xa7 => print!("ANA    A"),
-        0xa8 => print!("XRA    B"),
-        0xa9 => print!("XRA    C"),
-        0xaa => print!("XRA    D"),
-        0xab => print!("XRA    E"),
-        0xac => print!("XRA    H"),
-        0xad => print!("XRA    L"),
-        0xae => print!("XRA    M"),
-        0xaf => print!("XRA    A"),
-        0xb0 => print!("ORA    B"),
-        0xb1 => print!("ORA    C"),
-        0xb2 => print!("ORA    D"),
-        0xb3 => print!("ORA    E"),
-        0xb4 => print!("ORA    H"),
-        0xb5 => print!("ORA    L"),
-        0xb6 => print!("ORA    M"),
-        0xb7 => print!("ORA    A"),
-        0xb8 => print!("CMP    B"),
-        0xb9 => print!("CMP    C"),
-        0xba => print!("CMP    D"),
-        0xbb => print!("CMP    E"),
-        0xbc => print!("CMP    H"),
-        0xbd => print!("CMP    L"),
-        0xbe => print!("CMP    M"),
-        0xbf => print!("CMP    A"),
-        0xc0 => print!("RNZ"),
-        0xc1 => print!("POP    B"),
+        0x3f => write!(text, "CMC").unwrap(),
+        0x40 => write!(text, "MOV    B,B").unwrap(),
+        0x41 => write!(text, "MOV    B,C").unwrap(),
+        0x42 => write!(text, "MOV    B,D").unwrap(),
+        0x43 => write!(text, "MOV    B,E").unwrap(),
+        0x44 => write!(text, "MOV    B,H").unwrap(),
+        0x45 => write!(text, "MOV    B,L").unwrap(),
+        0x46 => write!(text, "MOV    B,M").unwrap(),
+        0x47 => write!(text, "MOV    B,A").unwrap(),
+        0x48 => write!(text, "MOV    C,B").unwrap(),
+        0x49 => write!(text, "MOV    C,C").unwrap(),
+        0x4a => write!(text, "MOV    C,D").unwrap(),
+        0x4b => write!(text, "MOV    C,E").unwrap(),
+        0x4c => write!(text, "MOV    C,H").unwrap(),
+        0x4d => write!(text, "MOV    C,L").unwrap(),
+        0x4e => write!(text, "MOV    C,M").unwrap(),
+        0x4f => write!(text, "MOV    C,A").unwrap(),
+        0x50 => write!(text, "MOV    D,B").unwrap(),
+        0x51 => write!(text, "MOV    D,C").unwrap(),
+        0x52 => write!(text, "MOV    D,D").unwrap(),
+        0x53 => write!(text, "MOV    D,E").unwrap(),
+        0x54 => write!(text, "MOV    D,H").unwrap(),
+        0x55 => write!(text, "MOV    D,L").unwrap(),
+        0x56 => write!(text, "MOV    D,M").unwrap(),
+        0x57 => write!(text, "MOV    D,A").unwrap(),
+        0x58 => write!(text, "MOV    E,B").unwrap(),
+        0x59 => write!(text, "MOV    E,C").unwrap(),
+        0x5a => write!(text, "MOV    E,D").unwrap(),
+        0x5b => write!(text, "MOV    E,E").unwrap(),
+        0x5c => write!(text, "MOV    E,H").unwrap(),
+        0x5d => write!(text, "MOV    E,L").unwrap(),
+        0x5e => write!(text, "MOV    E,M").unwrap(),
+        0x5f => write!(text, "MOV    E,A").unwrap(),
+        0x60 => write!(text, "MOV    H,B").unwrap(),
+        0x61 => write!(text, "MOV    H,C").unwrap(),
+        0x62 => write!(text, "MOV    H,D").unwrap(),
+        0x63 => write!(text, "MOV    H,E").unwrap(),
+        0x64 => write!(text, "MOV    H,H").unwrap(),
+        0x65 => write!(text, "MOV    H,L").unwrap(),
+        0x66 => write!(text, "MOV    H,M").unwrap(),
+        0x67 => write!(text, "MOV    H,A").unwrap(),
+        0x68 => write!(text, "MOV    L,B").unwrap(),
+        0x69 => write!(text, "MOV    L,C").unwrap(),
+        0x6a => write!(text, "MOV    L,D").unwrap(),
+        0x6b => write!(text, "MOV    L,E").unwrap(),
+        0x6c => write!(text, "MOV    L,H").unwrap(),
+        0x6d => write!(text, "MOV    L,L").unwrap(),
+        0x6e => write!(text, "MOV    L,M").unwrap(),
+        0x6f => write!(text, "MOV    L,A").unwrap(),
+        0x70 => write!(text, "MOV    M,B").unwrap(),
+        0x71 => write!(text, "MOV    M,C").unwrap(),
+        0x72 => write!(text, "MOV    M,D").unwrap(),
+        0x73 => write!(text, "MOV    M,E").unwrap(),
+        0x74 => write!(text, "MOV    M,H").unwrap(),
+        0x75 => write!(text, "MOV    M,L").unwrap(),
+        0x76 => write!(text, "HLT").unwrap(),
+        0x77 => write!(text, "MOV    M,A").unwrap(),
+        0x78 => write!(text, "MOV    A,B").unwrap(),
+        0x79 => write!(text, "MOV    A,C").unwrap(),
+        0x7a => write!(text, "MOV    A,D").unwrap(),
+        0x7b => write!(text, "MOV    A,E").unwrap(),
+        0x7c => write!(text, "MOV    A,H").unwrap(),
+        0x7d => write!(text, "MOV    A,L").unwrap(),
+        0x7e => write!(text, "MOV    A,M").unwrap(),
+        0x7f => write!(text, "MOV    A,A").unwrap(),
+        0x80 => write!(text, "ADD    B").unwrap(),
+        0x81 => write!(text, "ADD    C").unwrap(),
+        0x82 => write!(text, "ADD    D").unwrap(),
+        0x83 => write!(text, "ADD    E").unwrap(),
+        0x84 => write!(text, "ADD    H").unwrap(),
+        0x85 => write!(text, "ADD    L").unwrap(),
+        0x86 => write!(text, "ADD    M").unwrap(),
+        0x87 => write!(text, "ADD    A").unwrap(),
+        0x88 => write!(text, "ADC    B").unwrap(),
+        0x89 => write!(text, "ADC    C").unwrap(),
+        0x8a => write!(text, "ADC    D").unwrap(),
+        0x8b => write!(text, "ADC    E").unwrap(),
+        0x8c => write!(text, "ADC    H").unwrap(),
+        0x8d => write!(text, "ADC    L").unwrap(),
+        0x8e => write!(text, "ADC    M").unwrap(),
+        0x8f => write!(text, "ADC    A").unwrap(),
+        0x90 => write!(text, "SUB    B").unwrap(),
+        0x91 => write!(text, "SUB    C").unwrap(),
+        0x92 => write!(text, "SUB    D").unwrap(),
+        0x93 => write!(text, "SUB    E").unwrap(),
+        0x94 => write!(text, "SUB    H").unwrap(),
+        0x95 => write!(text, "SUB    L").unwrap(),
+        0x96 => write!(text, "SUB    M").unwrap(),
+        0x97 => write!(text, "SUB    A").unwrap(),
+        0x98 => write!(text, "SBB    B").unwrap(),
+        0x99 => write!(text, "SBB    C").unwrap(),
+        0x9a => write!(text, "SBB    D").unwrap(),
+        0x9b => write!(text, "SBB    E").unwrap(),
+        0x9c => write!(text, "SBB    H").unwrap(),
+        0x9d => write!(text, "SBB    L").unwrap(),
+        0x9e => write!(text, "SBB    M").unwrap(),
+        0x9f => write!(text, "SBB    A").unwrap(),
+        0xa0 => write!(text, "ANA    B").unwrap(),
+        0xa1 => write!(text, "ANA    C").unwrap(),
+        0xa2 => write!(text, "ANA    D").unwrap(),
+        0xa3 => write!(text, "ANA    E").unwrap(),
+        0xa4 => write!(text, "ANA    H").unwrap(),
+        0xa5 => write!(text, "ANA    L").unwrap(),
+        0xa6 => write!(text, "ANA    M").unwrap(),
+        0xa7 => write!(text, "ANA    A").unwrap(),
+        0xa8 => write!(text, "XRA    B").unwrap(),
+        0xa9 => write!(text, "XRA    C").unwrap(),
+        0xaa => write!(text, "XRA    D").unwrap(),
+        0xab => write!(text, "XRA    E").unwrap(),
+        0xac => write!(text, "XRA    H").unwrap(),
+        0xad => write!(text, "XRA    L").unwrap(),
+        0xae => write!(text, "XRA    M").unwrap(),
+        0xaf => write!(text, "XRA    A").unwrap(),
+        0xb0 => write!(text, "ORA    B").unwrap(),
+        0xb1 => write!(text, "ORA    C").unwrap(),
+        0xb2 => write!(text, "ORA    D").unwrap(),
+        0xb3 => write!(text, "ORA    E").unwrap(),
+        0xb4 => write!(text, "ORA    H").unwrap(),
+        0xb5 => write!(text, "ORA    L").unwrap(),
+        0xb6 => write!(text, "ORA    M").unwrap(),
+        0xb7 => write!(text, "ORA    A").unwrap(),
+        0xb8 => write!(text, "CMP    B").unwrap(),
+        0xb9 => write!(text, "CMP    C").unwrap(),
+        0xba => write!(text, "CMP    D").unwrap(),
+        0xbb => write!(text, "CMP    E").unwrap(),
+        0xbc => write!(text, "CMP    H").unwrap(),
+        0xbd => write!(text, "CMP    L").unwrap(),
+        0xbe => write!(text, "CMP    M").unwrap(),
+        0xbf => write!(text, "CMP    A").unwrap(),
+        0xc0 => write!(text, "RNZ").unwrap(),
+        0xc1 => write!(text, "POP    B").unwrap(),
         0xc2 => {
-            print!(
-                "JNZ    ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "JNZ    ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
         0xc3 => {
-            print!(
-                "JMP    ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "JMP    ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
         0xc4 => {
-            print!(
-                "CNZ    ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "CNZ    ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
-        0xc5 => print!("PUSH   B"),
+        0xc5 => write!(text, "PUSH   B").unwrap(),
         0xc6 => {
-            print!("ADI    #${:02x}", code_buffer[program_counter + 1]);
+            write!(text, "ADI    #${:02x}", byte(1)).unwrap();
             op_bytes = 2
         }
-        0xc7 => print!("RST    0"),
-        0xc8 => print!("RZ"),
-        0xc9 => print!("RET"),
+        0xc7 => write!(text, "RST    0").unwrap(),
+        0xc8 => write!(text, "RZ").unwrap(),
+        0xc9 => write!(text, "RET").unwrap(),
         0xca => {
-            print!(
-                "JZ     ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "JZ     ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
         0xcc => {
-            print!(
-                "CZ     ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "CZ     ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
         0xcd => {
-            print!(
-                "CALL   ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "CALL   ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
         0xce => {
-            print!("ACI    #${:02x}", code_buffer[program_counter + 1]);
+            write!(text, "ACI    #${:02x}", byte(1)).unwrap();
             op_bytes = 2
         }
-        0xcf => print!("RST    1"),
-        0xd0 => print!("RNC"),
-        0xd1 => print!("POP    D"),
+        0xcf => write!(text, "RST    1").unwrap(),
+        0xd0 => write!(text, "RNC").unwrap(),
+        0xd1 => write!(text, "POP    D").unwrap(),
         0xd2 => {
-            print!(
-                "JNC    ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "JNC    ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
         0xd3 => {
-            print!("OUT    #${:02x}", code_buffer[program_counter + 1]);
+            write!(text, "OUT    #${:02x}", byte(1)).unwrap();
             op_bytes = 2
         }
         0xd4 => {
-            print!(
-                "CNC    ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "CNC    ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
-        0xd5 => print!("PUSH   D"),
+        0xd5 => write!(text, "PUSH   D").unwrap(),
         0xd6 => {
-            print!("SUI    #${:02x}", code_buffer[program_counter + 1]);
+            write!(text, "SUI    #${:02x}", byte(1)).unwrap();
             op_bytes = 2
         }
-        0xd7 => print!("RST    2"),
-        0xd8 => print!("RC"),
+        0xd7 => write!(text, "RST    2").unwrap(),
+        0xd8 => write!(text, "RC").unwrap(),
         0xda => {
-            print!(
-                "JC     ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "JC     ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
         0xdb => {
-            print!("IN     #${:02x}", code_buffer[program_counter + 1]);
+            write!(text, "IN     #${:02x}", byte(1)).unwrap();
             op_bytes = 2
         }
         0xdc => {
-            print!(
-                "CC     ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "CC     ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
         0xde => {
-            print!("SBI    #${:02x}", code_buffer[program_counter + 1]);
+            write!(text, "SBI    #${:02x}", byte(1)).unwrap();
             op_bytes = 2
         }
-        0xdf => print!("RST    3"),
-        0xe0 => print!("RPO"),
-        0xe1 => print!("POP    H"),
+        0xdf => write!(text, "RST    3").unwrap(),
+        0xe0 => write!(text, "RPO").unwrap(),
+        0xe1 => write!(text, "POP    H").unwrap(),
         0xe2 => {
-            print!(
-                "JPO    ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "JPO    ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
-        0xe3 => print!("XTHL"),
+        0xe3 => write!(text, "XTHL").unwrap(),
         0xe4 => {
-            print!(
-                "CPO    ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "CPO    ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
-        0xe5 => print!("PUSH   H"),
+        0xe5 => write!(text, "PUSH   H").unwrap(),
         0xe6 => {
-            print!("ANI    #${:02x}", code_buffer[program_counter + 1]);
+            write!(text, "ANI    #${:02x}", byte(1)).unwrap();
             op_bytes = 2
         }
-        0xe7 => print!("RST    4"),
-        0xe8 => print!("RPE"),
-        0xe9 => print!("PCHL"),
+        0xe7 => write!(text, "RST    4").unwrap(),
+        0xe8 => write!(text, "RPE").unwrap(),
+        0xe9 => write!(text, "PCHL").unwrap(),
         0xea => {
-            print!(
-                "JPE    ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "JPE    ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
-        0xeb => print!("XCHG"),
+        0xeb => write!(text, "XCHG").unwrap(),
         0xec => {
-            print!(
-                "CPE    ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "CPE    ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
         0xee => {
-            print!("XRI    #${:02x}", code_buffer[program_counter + 1]);
+            write!(text, "XRI    #${:02x}", byte(1)).unwrap();
             op_bytes = 2
         }
-        0xef => print!("RST    5"),
-        0xf0 => print!("RP"),
-        0xf1 => print!("POP    PSW"),
+        0xef => write!(text, "RST    5").unwrap(),
+        0xf0 => write!(text, "RP").unwrap(),
+        0xf1 => write!(text, "POP    PSW").unwrap(),
         0xf2 => {
-            print!(
-                "JP     ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "JP     ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
-        0xf3 => print!("DI"),
+        0xf3 => write!(text, "DI").unwrap(),
         0xf4 => {
-            print!(
-                "CP     ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "CP     ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
-        0xf5 => print!("PUSH   PSW"),
+        0xf5 => write!(text, "PUSH   PSW").unwrap(),
         0xf6 => {
-            print!("ORI    #${:02x}", code_buffer[program_counter + 1]);
+            write!(text, "ORI    #${:02x}", byte(1)).unwrap();
             op_bytes = 2
         }
-        0xf7 => print!("RST    6"),
-        0xf8 => print!("RM"),
-        0xf9 => print!("SPHL"),
+        0xf7 => write!(text, "RST    6").unwrap(),
+        0xf8 => write!(text, "RM").unwrap(),
+        0xf9 => write!(text, "SPHL").unwrap(),
         0xfa => {
-            print!(
-                "JM     ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "JM     ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
-        0xfb => print!("EI"),
+        0xfb => write!(text, "EI").unwrap(),
         0xfc => {
-            print!(
-                "CM     ${:02x}{:02x}",
-                code_buffer[program_counter + 2],
-                code_buffer[program_counter + 1]
-            );
+            write!(text, "CM     ${:02x}{:02x}", byte(2), byte(1)).unwrap();
             op_bytes = 3
         }
         0xfe => {
-            print!("CPI    #${:02x}", code_buffer[program_counter + 1]);
+            write!(text, "CPI    #${:02x}", byte(1)).unwrap();
             op_bytes = 2
         }
-        0xff => print!("RST    7"),
+        0xff => write!(text, "RST    7").unwrap(),
     }
 
-    println!();
-
-    return op_bytes;
+    return (text, op_bytes);
 }
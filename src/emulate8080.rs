@@ -1,79 +1,217 @@
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use vulkano::buffer::Subbuffer;
 
-use crate::{disassemble::disassemble8080_op, i8080};
+use crate::{disassemble::disassemble8080_op, i8080, video};
+
+// default CPU clock, each MHz is 1,000,000 cycles per second
+pub const CYCLES_PER_SECOND: f64 = video::PIXEL_CLOCK / 5.0;
+// seconds of queued host audio to stay between
+const AUDIO_LOW_WATER: f64 = 0.05;
+const AUDIO_HIGH_WATER: f64 = 0.1;
 
 pub fn run_emulation(state: Arc<Mutex<i8080::State>>) {
     let mut should_exit = false;
-    let mut full_update = false;
-
-    // each MHz is 1,000,000 cycles per second
-    let cycles_per_second = 2.0 * 1_000_000.0;
-    let frames_per_second = 60.0;
-    let cycles_per_frame = (cycles_per_second / frames_per_second) as u32;
-
-    let mut last_interrupt = 0;
     let mut last_frame_cycles = 0;
 
     let start_time = std::time::SystemTime::now();
     let mut last_time = 0;
+    let mut audio_buffered = None;
+    let clock_speed = state.lock().unwrap().clock_speed;
 
     while !should_exit {
-        let mut cur_time = start_time.elapsed().unwrap().as_micros();
-        while cur_time - last_time < (last_frame_cycles as u128 / 2) {
-            cur_time = start_time.elapsed().unwrap().as_micros();
+        // with an audio device the buffer fill paces emulation instead of the clock
+        match audio_buffered {
+            Some(buffered) if buffered > AUDIO_HIGH_WATER => {
+                std::thread::sleep(Duration::from_secs_f64(buffered - AUDIO_HIGH_WATER));
+                audio_buffered = Some(AUDIO_HIGH_WATER);
+            }
+            Some(buffered) if buffered < AUDIO_LOW_WATER => (),
+            _ => {
+                let mut cur_time = start_time.elapsed().unwrap().as_micros();
+                let op_time = (last_frame_cycles as f64 * 1_000_000.0 / clock_speed) as u128;
+                while cur_time - last_time < op_time {
+                    cur_time = start_time.elapsed().unwrap().as_micros();
+                }
+            }
         }
         last_time = start_time.elapsed().unwrap().as_micros();
         {
             let mut state = state.lock().unwrap();
 
-            if last_interrupt > (cycles_per_frame / 2) {
-                if full_update {
-                    state.call_interrupt(2);
-                } else {
-                    state.call_interrupt(1);
-                }
-                full_update = !full_update;
-                last_interrupt = 0;
-            }
-
             if state.enable_stepping {
                 if state.step_count > 0 {
                     if state.step_count <= 10 {
-                        disassemble8080_op(&state.memory, state.program_counter as usize);
+                        disassemble8080_op(
+                            &state.memory,
+                            state.program_counter as usize,
+                            &state.patched,
+                        );
+                    }
+                    last_frame_cycles = profile8080_op(&mut state);
+                    if update_video(&mut state).is_some() {
+                        audio_buffered = update_audio(&mut state);
                     }
-                    last_frame_cycles = emulate8080_op(&mut state);
-                    last_interrupt += last_frame_cycles;
                     state.step_count -= 1;
                 }
             } else {
-                last_frame_cycles = emulate8080_op(&mut state);
-                last_interrupt += last_frame_cycles;
+                last_frame_cycles = profile8080_op(&mut state);
+                if update_video(&mut state).is_some() {
+                    audio_buffered = update_audio(&mut state);
+                }
+
+                if state.breakpoints.contains(&state.program_counter) {
+                    println!(
+                        "Breakpoint at {:04x} on scanline {}",
+                        state.program_counter,
+                        state.video.scanline(state.cycle_count)
+                    );
+                    state.break_debug_stepping();
+                }
             }
             should_exit = state.should_exit;
         }
     }
+
+    finish_emulation(&mut state.lock().unwrap());
+}
+
+// run without a window as fast as possible for a number of frames
+pub fn run_headless(state: &mut i8080::State, frames: u32) {
+    let mut flipped = state.screen_flipped();
+
+    for frame in 0..frames as u64 {
+        run_frame(state);
+        if state.should_exit {
+            break;
+        }
+
+        if state.screen_flipped() != flipped {
+            flipped = !flipped;
+            println!(
+                "Frame {}: screen {}",
+                frame,
+                if flipped { "flipped" } else { "upright" }
+            );
+        }
+    }
+
+    finish_emulation(state);
+}
+
+// run until the beam starts the next frame
+pub fn run_frame(state: &mut i8080::State) {
+    let next_frame = state.video.frames + 1;
+    while state.video.frames < next_frame && !state.should_exit {
+        profile8080_op(state);
+        if update_video(state).is_some() {
+            update_audio(state);
+        }
+    }
+}
+
+// reports and output files once emulation stops
+fn finish_emulation(state: &mut i8080::State) {
+    if let Some(script) = &state.script {
+        script.report();
+    }
+    if let Some(profiler) = &state.profiler {
+        profiler.print_report(&state.memory);
+    }
+    if let Some(call_profiler) = &state.call_profiler {
+        call_profiler.print_report();
+        if let Err(why) = call_profiler.write_folded() {
+            println!("Failed to write call profile: {}", why);
+        }
+    }
+    // main exits without dropping the state when a script fails
+    if let Some(tracer) = &mut state.tracer {
+        tracer.flush();
+    }
+    update_audio(state);
+    if let Some(audio) = &mut state.audio {
+        audio.finish();
+    }
+    if let Some(persistence) = &state.persistence {
+        if let Err(why) = persistence.save(state) {
+            println!("Failed to save persistent memory: {}", why);
+        }
+    }
+}
+
+// move the beam up to the current cycle, raising the interrupt of any line it passed,
+// the script steps and the controls are read into the ports at the start of each frame
+fn update_video(state: &mut i8080::State) -> Option<u8> {
+    let frames = state.video.frames;
+    let interrupt = state.video.advance(state.cycle_count, &state.memory);
+    if state.video.frames != frames {
+        if let Some(mut script) = state.script.take() {
+            script.run_frame(state);
+            state.script = Some(script);
+        }
+        state.update_inputs();
+    }
+    if let Some(code) = interrupt {
+        state.call_interrupt(code);
+    }
+
+    return interrupt;
+}
+
+// move PCM rendered up to the current cycle into the audio sink, returning its buffer fill
+fn update_audio(state: &mut i8080::State) -> Option<f64> {
+    let (Some(sound_board), Some(audio)) = (&mut state.sound_board, &mut state.audio) else {
+        return None;
+    };
+    sound_board.advance(state.cycle_count);
+    audio.queue(sound_board.take_output());
+
+    return audio.buffered();
+}
+
+// run one instruction, counting it in the profilers when enabled
+pub fn profile8080_op(state: &mut i8080::State) -> u32 {
+    let program_counter = state.program_counter;
+    let opcode = state.read_memory(program_counter);
+    // show where the CPU was before the panic reaches the thread
+    let cycles = match panic::catch_unwind(AssertUnwindSafe(|| emulate8080_op(state))) {
+        Ok(cycles) => cycles,
+        Err(payload) => {
+            state
+                .shadow_stack
+                .print_backtrace(&state.memory, state.program_counter);
+            panic::resume_unwind(payload);
+        }
+    };
+    if let Some(profiler) = &mut state.profiler {
+        profiler.record(program_counter, opcode, cycles);
+    }
+    if let Some(call_profiler) = &mut state.call_profiler {
+        call_profiler.record(&state.shadow_stack, cycles);
+    }
+
+    return cycles;
 }
 
 pub fn copy_screen_memory(state: &Arc<Mutex<i8080::State>>, upload_buffer: &Subbuffer<[u8]>) {
     let data = state.lock().unwrap();
 
+    // the frame captured by the beam rather than live video RAM
     let mut write = upload_buffer.write().unwrap();
-    for ind in 0..(256 * 28) {
-        for bit in 0..8 {
-            let value = ((data.memory[ind + 0x2400] >> bit) & 0x1) * 0xff;
-            write[ind * 32 + bit * 4] = value;
-            write[ind * 32 + bit * 4 + 1] = value;
-            write[ind * 32 + bit * 4 + 2] = value;
-            write[ind * 32 + bit * 4 + 3] = 0xff;
-        }
-    }
+    data.overlay
+        .render(&data.video, data.screen_flipped(), &mut write);
 }
 
 // call appropriate function for each code
 pub fn emulate8080_op(state: &mut i8080::State) -> u32 {
-    match state.memory[state.program_counter as usize] {
+    if let Some(mut tracer) = state.tracer.take() {
+        tracer.record(state);
+        state.tracer = Some(tracer);
+    }
+
+    let cycles = match state.read_memory(state.program_counter) {
         0x00 | 0x08 | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 | 0xcb | 0xd9 | 0xdd | 0xed
         | 0xfd => {
             state.program_counter += 1;
@@ -322,5 +460,8 @@ pub fn emulate8080_op(state: &mut i8080::State) -> u32 {
         0xfc => state.cm_call_if_minus(),
         0xfe => state.cpi_compare_immediate_to_accumulator(),
         0xff => state.rst_reset(7),
-    }
+    };
+    state.cycle_count += cycles as u64;
+
+    return cycles;
 }
@@ -0,0 +1,258 @@
+use std::thread;
+use std::time::Instant;
+
+use crate::emulate8080::run_frame;
+use crate::i8080::State;
+use crate::machine::GameRam;
+use crate::script::Script;
+use crate::video::BYTES_PER_LINE;
+
+// a minute of frames for the start script to get a game going
+const START_FRAMES: u64 = 60 * 60;
+
+// what an agent can do each step, as player 1's controls
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Action {
+    Noop,
+    Fire,
+    Left,
+    Right,
+    LeftFire,
+    RightFire,
+}
+
+pub const ACTIONS: [Action; 6] = [
+    Action::Noop,
+    Action::Fire,
+    Action::Left,
+    Action::Right,
+    Action::LeftFire,
+    Action::RightFire,
+];
+
+impl Action {
+    // held controls, left, right and fire
+    fn controls(self) -> [(&'static str, bool); 3] {
+        let (left, right, fire) = match self {
+            Action::Noop => (false, false, false),
+            Action::Fire => (false, false, true),
+            Action::Left => (true, false, false),
+            Action::Right => (false, true, false),
+            Action::LeftFire => (true, false, true),
+            Action::RightFire => (false, true, true),
+        };
+
+        return [("p1left", left), ("p1right", right), ("p1fire", fire)];
+    }
+}
+
+// gym style interface: reset to the start of a game, then step an action for frame_skip frames
+// observing the screen, the points scored and whether the game is over
+pub struct Environment {
+    state: State,
+    // the first frame of a game, every reset goes back here
+    start: State,
+    game_ram: GameRam,
+    pub frame_skip: u32,
+    // screen pixels per observation cell across and down, 1 for the whole screen
+    pub downsample: usize,
+    score: u32,
+}
+
+fn bcd(byte: u8) -> u32 {
+    ((byte >> 4) * 10 + (byte & 0x0f)) as u32
+}
+
+impl Environment {
+    // plays the machine's start script from a fork of a freshly reset state
+    pub fn new(state: &State, frame_skip: u32, downsample: usize) -> Result<Environment, String> {
+        let Some(game_ram) = state.machine.game_ram.clone() else {
+            return Err(format!(
+                "{} has no score and lives addresses",
+                state.machine.name
+            ));
+        };
+        let controls: Vec<&str> = state
+            .machine
+            .inputs
+            .iter()
+            .map(|input| input.name)
+            .collect();
+        for control in ["p1left", "p1right", "p1fire"] {
+            if !controls.contains(&control) {
+                return Err(format!("{} has no control {}", state.machine.name, control));
+            }
+        }
+
+        let mut start = state.fork();
+        let script = Script::parse("start script", game_ram.start_script, &controls)?;
+        start.script = Some(script);
+        while !start.script.as_ref().unwrap().finished() {
+            if start.video.frames >= START_FRAMES {
+                return Err(format!("no game started within {} frames", START_FRAMES));
+            }
+            run_frame(&mut start);
+        }
+        start.script = None;
+
+        let mut environment = Environment {
+            state: start.fork(),
+            start,
+            game_ram,
+            frame_skip: frame_skip.max(1),
+            downsample: downsample.max(1),
+            score: 0,
+        };
+        environment.score = environment.read_score();
+
+        Ok(environment)
+    }
+
+    // independent copy for a parallel rollout
+    pub fn fork(&self) -> Environment {
+        Environment {
+            state: self.state.fork(),
+            start: self.start.fork(),
+            game_ram: self.game_ram.clone(),
+            frame_skip: self.frame_skip,
+            downsample: self.downsample,
+            score: self.score,
+        }
+    }
+
+    fn read_score(&self) -> u32 {
+        let low = self.state.read_memory(self.game_ram.score);
+        let high = self.state.read_memory(self.game_ram.score + 1);
+        return bcd(high) * 100 + bcd(low);
+    }
+
+    fn is_done(&self) -> bool {
+        self.state.read_memory(self.game_ram.lives) == 0
+            || self.state.read_memory(self.game_ram.playing) == 0
+    }
+
+    pub fn reset(&mut self) -> Vec<u8> {
+        self.state = self.start.fork();
+        self.score = self.read_score();
+        return self.observation();
+    }
+
+    // the action is held for frame_skip frames, the reward is the points scored meanwhile
+    pub fn step(&mut self, action: Action) -> (Vec<u8>, u32, bool) {
+        for (control, held) in action.controls() {
+            self.state.input_map.set_control(control, held).unwrap();
+        }
+        self.state.update_inputs();
+
+        let mut done = false;
+        for _ in 0..self.frame_skip {
+            run_frame(&mut self.state);
+            done = self.is_done();
+            if done {
+                break;
+            }
+        }
+
+        // scores wrap at 9999
+        let score = self.read_score();
+        let reward = (score + 10000 - self.score) % 10000;
+        self.score = score;
+
+        return (self.observation(), reward, done);
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.state.video.frames
+    }
+
+    // cells across and down, for invaders 224x256 at a downsample of 1 and 56x64 at 4,
+    // a downsample that doesn't divide the screen leaves part filled cells at the right and bottom
+    pub fn observation_size(&self) -> (usize, usize) {
+        let (width, height) = self.state.overlay.size();
+        return (
+            width.div_ceil(self.downsample),
+            height.div_ceil(self.downsample),
+        );
+    }
+
+    // one byte per cell in rows from the top left, cells_across * cells_down long. The screen is
+    // upright as the player sees it, a rotated monitor is turned back and a cocktail flip is
+    // ignored. A cell covers downsample x downsample pixels of video RAM and is 1 when any of
+    // them are lit, else 0; overlays, color RAM and backgrounds are left out
+    pub fn observation(&self) -> Vec<u8> {
+        let (width, height) = self.state.overlay.size();
+        let (cells_across, cells_down) = self.observation_size();
+        let video_ram = &self.state.memory[self.state.machine.video_ram..];
+        let mut cells = vec![0; cells_across * cells_down];
+        for y in 0..height {
+            for x in 0..width {
+                let (line, bit) = self.state.overlay.beam_position(x, y);
+                if (video_ram[line * BYTES_PER_LINE + bit / 8] >> (bit % 8)) & 1 != 0 {
+                    cells[(y / self.downsample) * cells_across + x / self.downsample] = 1;
+                }
+            }
+        }
+
+        return cells;
+    }
+}
+
+// xorshift, so rollouts repeat for a seed
+fn next_random(seed: &mut u64) -> u64 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 7;
+    *seed ^= *seed << 17;
+    return *seed;
+}
+
+// random agents on forks of the environment, one thread each, reporting scores and speed
+pub fn run_random_rollouts(environment: &Environment, episodes: usize) {
+    let started = Instant::now();
+    let results: Vec<(u32, u64, u64)> = thread::scope(|scope| {
+        let rollouts: Vec<_> = (0..episodes)
+            .map(|episode| {
+                let mut environment = environment.fork();
+                scope.spawn(move || {
+                    let mut seed = 0x9e3779b97f4a7c15 ^ (episode as u64 + 1);
+                    environment.reset();
+                    let first_frame = environment.frames();
+                    let mut score = 0;
+                    let mut steps = 0;
+                    loop {
+                        let action = ACTIONS[next_random(&mut seed) as usize % ACTIONS.len()];
+                        let (_, reward, done) = environment.step(action);
+                        score += reward;
+                        steps += 1;
+                        if done {
+                            break;
+                        }
+                    }
+                    (score, steps, environment.frames() - first_frame)
+                })
+            })
+            .collect();
+        rollouts
+            .into_iter()
+            .map(|rollout| rollout.join().unwrap())
+            .collect()
+    });
+
+    let mut total_frames = 0;
+    for (episode, (score, steps, frames)) in results.iter().enumerate() {
+        println!(
+            "Episode {}: score {} in {} steps, {} frames",
+            episode + 1,
+            score,
+            steps,
+            frames
+        );
+        total_frames += frames;
+    }
+    let seconds = started.elapsed().as_secs_f64();
+    println!(
+        "{} frames in {:.2}s, {:.0} frames per second",
+        total_frames,
+        seconds,
+        total_frames as f64 / seconds
+    );
+}
@@ -0,0 +1,304 @@
+use std::fs;
+use std::io;
+
+// file formats that carry their own load addresses, or raw bytes
+#[derive(Clone, Copy, PartialEq)]
+pub enum HexFormat {
+    IntelHex,
+    SRecord,
+    Binary,
+}
+
+// 64K of memory with the loaded records in place
+pub struct HexImage {
+    pub memory: Vec<u8>,
+    pub start: Option<u16>,
+}
+
+// bytes per data record when writing
+const RECORD_LENGTH: usize = 16;
+
+impl HexFormat {
+    pub fn from_filename(filename: &str) -> HexFormat {
+        let extension = filename.rsplit('.').next().unwrap().to_ascii_lowercase();
+        match extension.as_str() {
+            "hex" | "ihx" | "ihex" => HexFormat::IntelHex,
+            "srec" | "s19" | "s28" | "s37" | "mot" => HexFormat::SRecord,
+            _ => HexFormat::Binary,
+        }
+    }
+}
+
+fn parse_bytes(hex: &str) -> Result<Vec<u8>, String> {
+    if !hex.len().is_multiple_of(2) {
+        return Err("odd number of hex digits".to_string());
+    }
+    (0..hex.len() / 2)
+        .map(|ind| match hex.get(ind * 2..ind * 2 + 2) {
+            Some(pair) => u8::from_str_radix(pair, 16).ok(),
+            None => None,
+        })
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| "invalid hex digits".to_string())
+}
+
+fn place(memory: &mut [u8], address: u32, data: &[u8]) -> Result<(), String> {
+    let end = address as usize + data.len();
+    if end > memory.len() {
+        return Err(format!(
+            "data at {:x} is outside the 64K address space",
+            address
+        ));
+    }
+    memory[address as usize..end].copy_from_slice(data);
+
+    Ok(())
+}
+
+fn big_endian(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |value, byte| (value << 8) | *byte as u32)
+}
+
+// ":" count, address, type, data and a checksum bringing the byte sum to zero,
+// returns true at the end of file record
+fn intel_hex_line(line: &str, base: &mut u32, image: &mut HexImage) -> Result<bool, String> {
+    let Some(hex) = line.strip_prefix(':') else {
+        return Err("record must start with :".to_string());
+    };
+    let bytes = parse_bytes(hex)?;
+    if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+        return Err("record length doesn't match its count".to_string());
+    }
+    if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+        return Err("bad checksum".to_string());
+    }
+
+    let address = big_endian(&bytes[1..3]);
+    let data = &bytes[4..bytes.len() - 1];
+    match (bytes[3], data.len()) {
+        (0x00, _) => place(&mut image.memory, *base + address, data)?,
+        (0x01, _) => return Ok(true),
+        (0x02, 2) => *base = big_endian(data) << 4,
+        // CS:IP
+        (0x03, 4) => {
+            image.start = Some(((big_endian(&data[0..2]) << 4) + big_endian(&data[2..4])) as u16)
+        }
+        (0x04, 2) => *base = big_endian(data) << 16,
+        (0x05, 4) => image.start = Some(big_endian(data) as u16),
+        (0x02..=0x05, _) => return Err("wrong length for record type".to_string()),
+        (record, _) => return Err(format!("unknown record type {:02x}", record)),
+    }
+
+    return Ok(false);
+}
+
+// "S" type, count, address, data and a checksum bringing the byte sum to ff
+fn s_record_line(line: &str, data_records: &mut u32, image: &mut HexImage) -> Result<(), String> {
+    let mut chars = line.chars();
+    let (Some('S'), Some(record)) = (chars.next(), chars.next()) else {
+        return Err("record must start with S and its type".to_string());
+    };
+    let address_length = match record {
+        '0' | '1' | '5' | '9' => 2,
+        '2' | '6' | '8' => 3,
+        '3' | '7' => 4,
+        _ => return Err(format!("unknown record type S{}", record)),
+    };
+    let bytes = parse_bytes(&line[2..])?;
+    if bytes.len() < address_length + 2 || bytes.len() != bytes[0] as usize + 1 {
+        return Err("record length doesn't match its count".to_string());
+    }
+    if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0xff {
+        return Err("bad checksum".to_string());
+    }
+
+    let address = big_endian(&bytes[1..1 + address_length]);
+    let data = &bytes[1 + address_length..bytes.len() - 1];
+    match record {
+        '1' | '2' | '3' => {
+            place(&mut image.memory, address, data)?;
+            *data_records += 1;
+        }
+        '5' | '6' if address != *data_records => {
+            return Err(format!(
+                "record count {} doesn't match the {} data records",
+                address, data_records
+            ))
+        }
+        '7' | '8' | '9' => image.start = Some(address as u16),
+        _ => (),
+    }
+
+    Ok(())
+}
+
+// error with its line number, 0 when it isn't about one line
+fn parse_records(
+    contents: &str,
+    format: HexFormat,
+    image: &mut HexImage,
+) -> Result<(), (usize, String)> {
+    let mut base = 0;
+    let mut data_records = 0;
+    for (ind, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let result = match format {
+            HexFormat::IntelHex => intel_hex_line(line, &mut base, image),
+            _ => s_record_line(line, &mut data_records, image).map(|_| false),
+        };
+        match result {
+            Ok(true) => return Ok(()),
+            Ok(false) => (),
+            Err(why) => return Err((ind + 1, why)),
+        }
+    }
+    if format == HexFormat::IntelHex {
+        return Err((0, "missing end of file record".to_string()));
+    }
+
+    Ok(())
+}
+
+// Intel HEX or S-records, as the filename implies
+pub fn read(filename: &str) -> Result<HexImage, String> {
+    let contents = match fs::read_to_string(filename) {
+        Ok(res) => res,
+        Err(why) => return Err(format!("{}: {}", filename, why)),
+    };
+    let mut image = HexImage {
+        memory: vec![0; 0x10000],
+        start: None,
+    };
+
+    match parse_records(&contents, HexFormat::from_filename(filename), &mut image) {
+        Ok(()) => Ok(image),
+        Err((0, why)) => Err(format!("{}: {}", filename, why)),
+        Err((line, why)) => Err(format!("{}:{}: {}", filename, line, why)),
+    }
+}
+
+fn intel_hex_record(record: u8, address: u16, data: &[u8]) -> String {
+    let mut bytes = vec![
+        data.len() as u8,
+        (address >> 8) as u8,
+        address as u8,
+        record,
+    ];
+    bytes.extend_from_slice(data);
+    let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    bytes.push(sum.wrapping_neg());
+
+    let hex: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    return format!(":{}\n", hex);
+}
+
+fn s_record(record: char, address: u16, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8 + 3, (address >> 8) as u8, address as u8];
+    bytes.extend_from_slice(data);
+    let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    bytes.push(!sum);
+
+    let hex: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    return format!("S{}{}\n", record, hex);
+}
+
+// bytes read from start onwards, in the format the filename implies
+pub fn write(filename: &str, start: u16, bytes: &[u8]) -> io::Result<()> {
+    let format = HexFormat::from_filename(filename);
+    if format == HexFormat::Binary {
+        return fs::write(filename, bytes);
+    }
+
+    fs::write(filename, records(format, start, bytes))
+}
+
+fn records(format: HexFormat, start: u16, bytes: &[u8]) -> String {
+    let mut contents = String::new();
+    if format == HexFormat::SRecord {
+        contents += &s_record('0', 0, b"i8080");
+    }
+    let records = bytes.chunks(RECORD_LENGTH);
+    let record_count = records.len();
+    for (ind, data) in records.enumerate() {
+        let address = start.wrapping_add((ind * RECORD_LENGTH) as u16);
+        contents += &match format {
+            HexFormat::IntelHex => intel_hex_record(0x00, address, data),
+            _ => s_record('1', address, data),
+        };
+    }
+    contents += &match format {
+        HexFormat::IntelHex => intel_hex_record(0x01, 0, &[]),
+        _ => s_record('5', record_count as u16, &[]) + &s_record('9', 0, &[]),
+    };
+
+    return contents;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &str, format: HexFormat) -> Result<HexImage, (usize, String)> {
+        let mut image = HexImage {
+            memory: vec![0; 0x10000],
+            start: None,
+        };
+        parse_records(contents, format, &mut image)?;
+
+        Ok(image)
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let bytes: Vec<u8> = (0..40).map(|byte| byte * 5).collect();
+        for format in [HexFormat::IntelHex, HexFormat::SRecord] {
+            let image = parse(&records(format, 0x1ff8, &bytes), format).unwrap();
+            assert_eq!(image.memory[0x1ff8..0x1ff8 + bytes.len()], bytes[..]);
+            assert!(image.memory[..0x1ff8].iter().all(|byte| *byte == 0));
+            assert!(image.memory[0x1ff8 + bytes.len()..]
+                .iter()
+                .all(|byte| *byte == 0));
+        }
+    }
+
+    #[test]
+    fn intel_hex_records() {
+        let contents = ":0300300002337A1E\n:04000005000000FDFA\n:00000001FF\n";
+        let image = parse(contents, HexFormat::IntelHex).unwrap();
+        assert_eq!(image.memory[0x30..0x33], [0x02, 0x33, 0x7a]);
+        assert_eq!(image.start, Some(0xfd));
+
+        let missing_end = ":0300300002337A1E\n";
+        assert!(parse(missing_end, HexFormat::IntelHex).is_err());
+    }
+
+    #[test]
+    fn bad_checksums() {
+        let intel_hex = ":0300300002337A1F\n:00000001FF\n";
+        assert_eq!(
+            parse(intel_hex, HexFormat::IntelHex).err(),
+            Some((1, "bad checksum".to_string()))
+        );
+
+        let s_record = "S00600004844521B\nS1130000285F245F2212226A000424290008237C2B\n";
+        assert_eq!(
+            parse(s_record, HexFormat::SRecord).err(),
+            Some((2, "bad checksum".to_string()))
+        );
+    }
+
+    #[test]
+    fn s_record_count() {
+        let contents = records(HexFormat::SRecord, 0, &[1, 2, 3]);
+        assert!(parse(&contents, HexFormat::SRecord).is_ok());
+
+        let wrong_count = contents.replace("S5030001FB", "S5030002FA");
+        assert_ne!(wrong_count, contents);
+        assert!(parse(&wrong_count, HexFormat::SRecord).is_err());
+    }
+}
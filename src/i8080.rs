@@ -3,8 +3,28 @@ mod io_ops;
 mod jump;
 mod load;
 mod math;
+mod memory;
 mod stack;
 
+pub use memory::{MemoryMap, RomWritePolicy};
+
+use crate::audio::AudioOutput;
+use crate::callstack::ShadowStack;
+use crate::dip_switches::DipSwitches;
+use crate::emulate8080::CYCLES_PER_SECOND;
+use crate::input::InputMap;
+use crate::machine::{self, MachineDriver};
+use crate::overlay::Overlay;
+use crate::persistence::Persistence;
+use crate::profiler::{CallProfiler, Profiler};
+use crate::script::Script;
+use crate::shift_register::{ShiftRegister, SHIFT_REGISTER_SIZE};
+use crate::sound::{SoundBoard, SoundLatches};
+use crate::trace::Tracer;
+use crate::video::Video;
+
+const SNAPSHOT_MAGIC: &[u8; 8] = b"I8080SS\x01";
+
 pub enum RegisterSymbols {
     A,
     B,
@@ -18,6 +38,7 @@ pub enum RegisterSymbols {
     MEMORY,
 }
 
+#[derive(Clone)]
 pub struct Flags {
     zero: bool,
     sign: bool,
@@ -44,11 +65,37 @@ pub struct State {
     pub step_count: u16,
     pub enable_stepping: bool,
     pub in_ports: [u8; 4],
-    shift_amount: u8,
+    pub input_map: InputMap,
+    pub shift_register: ShiftRegister,
+    pub cycle_count: u64,
+    pub tracer: Option<Tracer>,
+    pub profiler: Option<Profiler>,
+    pub shadow_stack: ShadowStack,
+    pub call_profiler: Option<CallProfiler>,
+    pub breakpoints: Vec<u16>,
+    pub memory_map: MemoryMap,
+    pub rom_write_policy: RomWritePolicy,
+    pub dip_switches: DipSwitches,
+    pub sound_latches: SoundLatches,
+    pub sound_board: Option<SoundBoard>,
+    pub audio: Option<AudioOutput>,
+    pub video: Video,
+    pub overlay: Overlay,
+    pub cocktail: bool,
+    // CPU cycles per second of real time
+    pub clock_speed: f64,
+    pub persistence: Option<Persistence>,
+    pub script: Option<Script>,
+    // bytes of memory that came from a patch rather than the ROM
+    pub patched: Vec<bool>,
+    // ports read with nothing mapped, each is reported once
+    pub unmapped_reads: Vec<u8>,
+    pub machine: MachineDriver,
 }
 
 impl State {
     pub fn new(mem: Vec<u8>, test: bool) -> State {
+        let machine = machine::space_invaders();
         State {
             reg_a: 0,
             reg_b: 0,
@@ -72,9 +119,183 @@ impl State {
             should_exit: false,
             step_count: 1,
             enable_stepping: false,
-            in_ports: [0b00001110, 0b00001000, 0b00000000, 0],
-            shift_amount: 0,
+            in_ports: machine.port_defaults,
+            input_map: InputMap::new(&machine.inputs),
+            shift_register: ShiftRegister::new(),
+            cycle_count: 0,
+            tracer: None,
+            profiler: None,
+            shadow_stack: ShadowStack::new(),
+            call_profiler: None,
+            breakpoints: Vec::new(),
+            memory_map: if test {
+                MemoryMap::Flat
+            } else {
+                machine.memory_map.clone()
+            },
+            rom_write_policy: RomWritePolicy::Ignore,
+            dip_switches: DipSwitches::new(machine.dip_switches.clone()),
+            sound_latches: SoundLatches::new(),
+            sound_board: None,
+            audio: None,
+            video: Video::new(
+                machine.video_ram,
+                machine.memory_map.color_address(machine.video_ram as u16),
+            ),
+            overlay: Overlay::new(),
+            cocktail: false,
+            clock_speed: CYCLES_PER_SECOND,
+            persistence: None,
+            script: None,
+            patched: Vec::new(),
+            unmapped_reads: Vec::new(),
+            machine,
+        }
+    }
+
+    // swap the board hardware for another game's, before reset
+    pub fn set_machine(&mut self, machine: MachineDriver) {
+        if !self.testing {
+            self.memory_map = machine.memory_map.clone();
+        }
+        self.in_ports = machine.port_defaults;
+        self.input_map = InputMap::new(&machine.inputs);
+        self.dip_switches = DipSwitches::new(machine.dip_switches.clone());
+        self.video = Video::new(
+            machine.video_ram,
+            machine.memory_map.color_address(machine.video_ram as u16),
+        );
+        self.overlay.rotated = machine.rotated;
+        self.machine = machine;
+    }
+
+    // copy of the machine for running ahead from this point, without the tracer, profilers,
+    // sound output, persistence or script attached to this one
+    pub fn fork(&self) -> State {
+        State {
+            reg_a: self.reg_a,
+            reg_b: self.reg_b,
+            reg_c: self.reg_c,
+            reg_d: self.reg_d,
+            reg_e: self.reg_e,
+            reg_h: self.reg_h,
+            reg_l: self.reg_l,
+            stack_pointer: self.stack_pointer,
+            program_counter: self.program_counter,
+            flags: self.flags.clone(),
+            memory: self.memory.clone(),
+            testing: self.testing,
+            should_exit: self.should_exit,
+            step_count: self.step_count,
+            enable_stepping: false,
+            in_ports: self.in_ports,
+            input_map: self.input_map.clone(),
+            shift_register: self.shift_register,
+            cycle_count: self.cycle_count,
+            tracer: None,
+            profiler: None,
+            shadow_stack: self.shadow_stack.clone(),
+            call_profiler: None,
+            breakpoints: Vec::new(),
+            memory_map: self.memory_map.clone(),
+            rom_write_policy: self.rom_write_policy.clone(),
+            dip_switches: self.dip_switches.clone(),
+            sound_latches: self.sound_latches.clone(),
+            sound_board: None,
+            audio: None,
+            video: self.video.clone(),
+            overlay: self.overlay.clone(),
+            cocktail: self.cocktail,
+            clock_speed: self.clock_speed,
+            persistence: None,
+            script: None,
+            patched: self.patched.clone(),
+            unmapped_reads: self.unmapped_reads.clone(),
+            machine: self.machine.clone(),
+        }
+    }
+
+    // reset line: execution restarts at 0, the beam at line 0 and the DIP switches are read again,
+    // persistent memory is restored again once the game reinitializes it
+    pub fn reset(&mut self) {
+        self.program_counter = 0;
+        self.flags.interrupts_enabled = false;
+        self.shadow_stack = ShadowStack::new();
+        self.video.reset(self.cycle_count);
+        if let Some(mut persistence) = self.persistence.take() {
+            persistence.reset(self);
+            self.persistence = Some(persistence);
         }
+        self.update_inputs();
+    }
+
+    // registers, flags, shift register, sound latches and memory, after the magic and the
+    // machine's name, the cycle count isn't kept so timing runs on from where it is
+    pub fn save_snapshot(&self) -> Vec<u8> {
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        bytes.push(self.machine.name.len() as u8);
+        bytes.extend_from_slice(self.machine.name.as_bytes());
+        bytes.extend_from_slice(&[
+            self.reg_a,
+            self.flags_to_u8(),
+            self.reg_b,
+            self.reg_c,
+            self.reg_d,
+            self.reg_e,
+            self.reg_h,
+            self.reg_l,
+        ]);
+        bytes.extend_from_slice(&self.stack_pointer.to_le_bytes());
+        bytes.extend_from_slice(&self.program_counter.to_le_bytes());
+        bytes.push(self.flags.interrupts_enabled as u8);
+        bytes.extend_from_slice(&self.shift_register.to_bytes());
+        bytes.extend_from_slice(&[self.sound_latches.port3, self.sound_latches.port5]);
+        bytes.extend_from_slice(&self.memory);
+
+        return bytes;
+    }
+
+    // the beam restarts a frame as after a reset, the state is unchanged on error
+    pub fn load_snapshot(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let Some(rest) = bytes.strip_prefix(SNAPSHOT_MAGIC) else {
+            return Err("not a snapshot".to_string());
+        };
+        let name_length = *rest.first().unwrap_or(&0) as usize;
+        let name = rest.get(1..1 + name_length).unwrap_or_default();
+        if name != self.machine.name.as_bytes() {
+            return Err(format!(
+                "snapshot is of {}, not {}",
+                String::from_utf8_lossy(name),
+                self.machine.name
+            ));
+        }
+        let rest = &rest[1 + name_length..];
+        // registers, SP, PC and the interrupt enable come first
+        let memory_start = 13 + SHIFT_REGISTER_SIZE + 2;
+        if rest.len() != memory_start + self.memory.len() {
+            return Err("snapshot doesn't match the memory size".to_string());
+        }
+
+        let registers = &rest[0..8];
+        self.reg_a = registers[0];
+        self.u8_to_flags(registers[1]);
+        self.reg_b = registers[2];
+        self.reg_c = registers[3];
+        self.reg_d = registers[4];
+        self.reg_e = registers[5];
+        self.reg_h = registers[6];
+        self.reg_l = registers[7];
+        self.stack_pointer = u16::from_le_bytes([rest[8], rest[9]]);
+        self.program_counter = u16::from_le_bytes([rest[10], rest[11]]);
+        self.flags.interrupts_enabled = rest[12] != 0;
+        self.shift_register = ShiftRegister::from_bytes(&rest[13..13 + SHIFT_REGISTER_SIZE]);
+        self.sound_latches.port3 = rest[memory_start - 2];
+        self.sound_latches.port5 = rest[memory_start - 1];
+        self.memory.copy_from_slice(&rest[memory_start..]);
+        self.shadow_stack = ShadowStack::new();
+        self.video.reset(self.cycle_count);
+
+        Ok(())
     }
 
     pub fn check_and_print_call(&mut self) {
@@ -94,7 +315,7 @@ impl State {
     }
 
     fn get_next(&mut self, offset: u16) -> u8 {
-        self.memory[(self.program_counter + offset) as usize]
+        self.read_memory(self.program_counter + offset)
     }
 
     fn get_next_word(&mut self) -> u16 {
@@ -180,7 +401,7 @@ impl State {
             RegisterSymbols::H => self.reg_h,
             RegisterSymbols::L => self.reg_l,
             RegisterSymbols::A => self.reg_a,
-            RegisterSymbols::MEMORY => self.memory[self.hl_to_address()],
+            RegisterSymbols::MEMORY => self.read_memory(self.hl_to_address() as u16),
             _ => panic!("Invalid register given"),
         }
     }
@@ -196,22 +417,22 @@ impl State {
             RegisterSymbols::A => self.reg_a = value,
             RegisterSymbols::MEMORY => {
                 let address = self.hl_to_address();
-                self.memory[address] = value
+                self.write_memory(address as u16, value)
             }
             _ => panic!("Invalid register given"),
         }
     }
 
     fn pop_stack(&mut self) -> u16 {
-        let res = (self.memory[self.stack_pointer as usize] as u16)
-            | ((self.memory[(self.stack_pointer + 1) as usize] as u16) << 8);
+        let res = (self.read_memory(self.stack_pointer) as u16)
+            | ((self.read_memory(self.stack_pointer + 1) as u16) << 8);
         self.stack_pointer += 2;
         res
     }
 
     fn push_stack(&mut self, value: u16) {
-        self.memory[(self.stack_pointer - 1) as usize] = ((value >> 8) & 0xff) as u8;
-        self.memory[(self.stack_pointer - 2) as usize] = (value & 0xff) as u8;
+        self.write_memory(self.stack_pointer - 1, ((value >> 8) & 0xff) as u8);
+        self.write_memory(self.stack_pointer - 2, (value & 0xff) as u8);
         self.stack_pointer -= 2;
     }
 
@@ -258,3 +479,48 @@ impl State {
         return 4;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::State;
+
+    #[test]
+    fn snapshot_round_trip() {
+        let mut state = State::new(vec![0; 0x10000], false);
+        state.reg_a = 0x12;
+        state.reg_l = 0x34;
+        state.u8_to_flags(0xd5);
+        state.stack_pointer = 0x2400;
+        state.program_counter = 0x1a5c;
+        state.flags.interrupts_enabled = true;
+        state.shift_register.shift_in(0xab);
+        state.shift_register.set_offset(3);
+        state.sound_latches.port3 = 0x02;
+        state.memory[0x2000] = 0x99;
+        let snapshot = state.save_snapshot();
+
+        let mut restored = State::new(vec![0; 0x10000], false);
+        restored.load_snapshot(&snapshot).unwrap();
+        assert_eq!(restored.reg_a, 0x12);
+        assert_eq!(restored.reg_l, 0x34);
+        assert_eq!(restored.flags_to_u8(), state.flags_to_u8());
+        assert_eq!(restored.stack_pointer, 0x2400);
+        assert_eq!(restored.program_counter, 0x1a5c);
+        assert!(restored.flags.interrupts_enabled);
+        assert_eq!(restored.shift_register.read(), state.shift_register.read());
+        assert_eq!(restored.sound_latches.port3, 0x02);
+        assert_eq!(restored.memory, state.memory);
+        assert_eq!(restored.save_snapshot(), snapshot);
+    }
+
+    #[test]
+    fn snapshot_rejects_other_files() {
+        let mut state = State::new(vec![0; 0x10000], false);
+        let snapshot = state.save_snapshot();
+        assert!(state
+            .load_snapshot(&snapshot[..snapshot.len() - 1])
+            .is_err());
+        assert!(state.load_snapshot(b"I8080SS").is_err());
+        assert!(state.load_snapshot(&[]).is_err());
+    }
+}
@@ -1,30 +1,26 @@
+use crate::callstack::{Frame, FrameKind};
 use crate::i8080::State;
+use crate::machine::PortDevice;
+use crate::sound::SoundEvent;
 
 impl State {
     // OUT d8
     pub fn out_send_output(&mut self) -> u32 {
         let port = self.get_next(1);
 
-        match port {
-            0x02 => {
-                // Shift
-                self.shift_amount = self.reg_a & 0b111;
+        match self.machine.write_device(port) {
+            Some(PortDevice::ShiftOffset) => self.shift_register.set_offset(self.reg_a),
+            Some(PortDevice::ShiftData) => self.shift_register.shift_in(self.reg_a),
+            Some(PortDevice::InvadersSound1) => {
+                let events = self.sound_latches.write_port3(self.reg_a);
+                self.play_sound_events(events);
             }
-            0x03 => {
-                // Sound
-                println!("Sound played as {:08b}", self.reg_a);
-            }
-            0x04 => {
-                self.in_ports[0x03] = self.reg_a << self.shift_amount;
-            }
-            0x05 => {
-                // Sound
-                println!("Sound played as {:08b}", self.reg_a);
-            }
-            0x06 => {
-                // watchdog
-                ()
+            Some(PortDevice::InvadersSound2) => {
+                // bit 5 also flips the screen on cocktail tables
+                let events = self.sound_latches.write_port5(self.reg_a);
+                self.play_sound_events(events);
             }
+            Some(PortDevice::Watchdog) | Some(PortDevice::Unconnected) => (),
             _ => panic!("OUT on unimplemented port: {:02x}", port),
         }
 
@@ -33,11 +29,60 @@ impl State {
         return 10;
     }
 
+    fn play_sound_events(&mut self, events: Vec<SoundEvent>) {
+        if let Some(sound_board) = &mut self.sound_board {
+            for event in events {
+                sound_board.trigger(event, self.cycle_count);
+            }
+        }
+    }
+
+    pub fn screen_flipped(&self) -> bool {
+        self.cocktail && (self.sound_latches.port5 & 0b00100000) != 0
+    }
+
+    // port bits from the held controls and the DIP switches
+    pub fn update_inputs(&mut self) {
+        let mut ports = self.machine.port_defaults;
+        for input in self.machine.inputs.iter() {
+            if !self.input_map.is_held(input.name) {
+                continue;
+            }
+            let mut wired = vec![input.port];
+            if let (Some(port), false) = (input.upright_port, self.cocktail) {
+                wired.push(port);
+            }
+            for port in wired {
+                if input.active_low {
+                    ports[port] &= !input.bits;
+                } else {
+                    ports[port] |= input.bits;
+                }
+            }
+        }
+        for (port, value) in ports.iter_mut().enumerate() {
+            *value = self.dip_switches.apply(port, *value);
+        }
+
+        self.in_ports = ports;
+    }
+
     // IN d8
     pub fn in_update_input(&mut self) -> u32 {
         let port = self.get_next(1) as usize;
 
-        self.reg_a = self.in_ports[port];
+        self.reg_a = match self.machine.read_device(port as u8) {
+            Some(PortDevice::ShiftResult) => self.shift_register.read(),
+            Some(PortDevice::Inputs(index)) => self.in_ports[index],
+            // nothing drives the data bus, its pull-ups read as all ones
+            _ => {
+                if !self.unmapped_reads.contains(&(port as u8)) {
+                    println!("IN on unmapped port {:02x}, reading open bus ff", port);
+                    self.unmapped_reads.push(port as u8);
+                }
+                0xff
+            }
+        };
         self.program_counter += 2;
 
         return 10;
@@ -48,6 +93,13 @@ impl State {
         let rst_loc = (code as u16).wrapping_mul(8);
 
         self.push_stack(self.program_counter + 1);
+        self.shadow_stack.push(Frame {
+            function: rst_loc,
+            call_site: self.program_counter,
+            return_address: self.program_counter + 1,
+            slot: self.stack_pointer,
+            kind: FrameKind::Restart,
+        });
         self.program_counter = rst_loc;
 
         return 11;
@@ -57,7 +109,8 @@ impl State {
         if self.flags.interrupts_enabled {
             self.flags.interrupts_enabled = false;
             self.program_counter -= 1;
-            self.rst_reset(code);
+            self.cycle_count += self.rst_reset(code) as u64;
+            self.shadow_stack.mark_interrupt();
         }
     }
 
@@ -65,6 +118,16 @@ impl State {
         println!("Enabling stepping");
         self.enable_stepping = true;
         self.step_count = 1;
+        self.shadow_stack
+            .print_backtrace(&self.memory, self.program_counter);
+    }
+
+    // stop before the next instruction without stepping past it
+    pub fn break_debug_stepping(&mut self) {
+        if !self.enable_stepping {
+            self.start_debug_stepping();
+            self.step_count = 0;
+        }
     }
 
     pub fn stop_debug_stepping(&mut self) {
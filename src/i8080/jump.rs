@@ -1,3 +1,4 @@
+use crate::callstack::{Frame, FrameKind};
 use crate::i8080::State;
 
 impl State {
@@ -64,6 +65,13 @@ impl State {
         let ret = self.program_counter + 3;
 
         self.push_stack(ret);
+        self.shadow_stack.push(Frame {
+            function: call_to,
+            call_site: self.program_counter,
+            return_address: ret,
+            slot: self.stack_pointer,
+            kind: FrameKind::Call,
+        });
 
         self.program_counter = call_to;
 
@@ -121,6 +129,7 @@ impl State {
 
     // RET
     pub fn ret_function_return(&mut self) -> u32 {
+        self.shadow_stack.pop(self.stack_pointer);
         self.program_counter = self.pop_stack();
 
         return 10;
@@ -43,7 +43,7 @@ impl State {
     // LDA adr
     pub fn lda_load_accumulator_direct(&mut self) -> u32 {
         let address = self.get_next_word();
-        self.reg_a = self.memory[address as usize];
+        self.reg_a = self.read_memory(address);
 
         self.program_counter += 3;
 
@@ -53,7 +53,7 @@ impl State {
     // LDAX reg
     pub fn ldax_load_accumulator_indirect(&mut self, register: RegisterSymbols) -> u32 {
         let address = self.get_pair_b_d_register(&register);
-        self.reg_a = self.memory[address as usize];
+        self.reg_a = self.read_memory(address);
 
         self.program_counter += 1;
 
@@ -74,8 +74,8 @@ impl State {
     pub fn lhld_load_hl_direct(&mut self) -> u32 {
         let address = self.get_next_word();
 
-        self.reg_h = self.memory[(address + 1) as usize];
-        self.reg_l = self.memory[address as usize];
+        self.reg_h = self.read_memory(address + 1);
+        self.reg_l = self.read_memory(address);
 
         self.program_counter += 3;
 
@@ -85,7 +85,7 @@ impl State {
     // STA adr
     pub fn sta_store_accumulator(&mut self) -> u32 {
         let address = self.get_next_word();
-        self.memory[address as usize] = self.reg_a;
+        self.write_memory(address, self.reg_a);
 
         self.program_counter += 3;
 
@@ -95,7 +95,7 @@ impl State {
     // STAX reg
     pub fn stax_store_accumulator_indirect(&mut self, register: RegisterSymbols) -> u32 {
         let address = self.get_pair_b_d_register(&register);
-        self.memory[address as usize] = self.reg_a;
+        self.write_memory(address, self.reg_a);
 
         self.program_counter += 1;
 
@@ -106,8 +106,8 @@ impl State {
     pub fn shld_store_hl_direct(&mut self) -> u32 {
         let address = self.get_next_word();
 
-        self.memory[(address + 1) as usize] = self.reg_h;
-        self.memory[address as usize] = self.reg_l;
+        self.write_memory(address + 1, self.reg_h);
+        self.write_memory(address, self.reg_l);
 
         self.program_counter += 3;
 
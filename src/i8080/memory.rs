@@ -0,0 +1,126 @@
+use std::io;
+
+use crate::hexfile;
+use crate::i8080::State;
+
+#[derive(Clone)]
+pub enum MemoryMap {
+    // all 64K readable and writable, used by the CP/M test programs
+    Flat,
+    // inclusive ROM ranges and a RAM block, addresses outside both mirror the RAM
+    Board {
+        rom: Vec<(u16, u16)>,
+        ram_start: u16,
+        ram_mask: u16,
+        // start of an optional color RAM window the size of the RAM, one byte per 8x8 cell
+        color_ram: Option<u16>,
+    },
+}
+
+#[derive(Clone)]
+pub enum RomWritePolicy {
+    Ignore,
+    Log,
+    Trap,
+}
+
+// the window mirrors the bitmap layout, every 8 lines of 32 bytes share one row of cells
+fn color_cell(offset: u16) -> usize {
+    return (((offset >> 8) << 5) | (offset & 0x1f)) as usize;
+}
+
+impl MemoryMap {
+    // index into the backing memory for a CPU address
+    fn resolve(&self, address: u16) -> usize {
+        match self {
+            MemoryMap::Flat => address as usize,
+            MemoryMap::Board {
+                ram_start,
+                ram_mask,
+                color_ram,
+                ..
+            } => {
+                if let Some(color_ram) = color_ram {
+                    let offset = address.wrapping_sub(*color_ram);
+                    if offset <= *ram_mask {
+                        return *color_ram as usize + color_cell(offset);
+                    }
+                }
+
+                let in_ram = (*ram_start..=ram_start + ram_mask).contains(&address);
+                if in_ram || self.is_rom(address) {
+                    address as usize
+                } else {
+                    (ram_start | (address & ram_mask)) as usize
+                }
+            }
+        }
+    }
+
+    // backing index of the color cell over a bitmap byte
+    pub fn color_address(&self, address: u16) -> Option<usize> {
+        match self {
+            MemoryMap::Board {
+                ram_mask,
+                color_ram: Some(color_ram),
+                ..
+            } => Some(*color_ram as usize + color_cell(address & ram_mask)),
+            _ => None,
+        }
+    }
+
+    fn is_rom(&self, address: u16) -> bool {
+        match self {
+            MemoryMap::Flat => false,
+            MemoryMap::Board { rom, .. } => rom
+                .iter()
+                .any(|(start, end)| (*start..=*end).contains(&address)),
+        }
+    }
+}
+
+impl State {
+    pub fn read_memory(&self, address: u16) -> u8 {
+        self.memory[self.memory_map.resolve(address)]
+    }
+
+    // an inclusive range as the CPU sees it, format from the filename
+    pub fn export_memory(&self, start: u16, end: u16, filename: &str) -> io::Result<()> {
+        let bytes: Vec<u8> = (start..=end)
+            .map(|address| self.read_memory(address))
+            .collect();
+        hexfile::write(filename, start, &bytes)
+    }
+
+    pub fn write_memory(&mut self, address: u16, value: u8) {
+        if self.memory_map.is_rom(address) {
+            match self.rom_write_policy {
+                RomWritePolicy::Ignore => (),
+                RomWritePolicy::Log => println!(
+                    "ROM write of {:02x} to {:04x} at PC: {:04x}",
+                    value, address, self.program_counter
+                ),
+                RomWritePolicy::Trap => {
+                    println!(
+                        "ROM write of {:02x} to {:04x} at PC: {:04x}",
+                        value, address, self.program_counter
+                    );
+                    self.break_debug_stepping();
+                }
+            }
+            return;
+        }
+
+        let index = self.memory_map.resolve(address);
+        self.memory[index] = value;
+
+        if let Some(persistence) = &mut self.persistence {
+            if let Some((start, bytes)) = persistence.record_write(index as u16) {
+                for (offset, byte) in bytes.iter().enumerate() {
+                    let index = self.memory_map.resolve(start.wrapping_add(offset as u16));
+                    self.memory[index] = *byte;
+                }
+            }
+        }
+    }
+}
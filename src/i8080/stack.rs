@@ -23,6 +23,7 @@ impl State {
     // SPHL
     pub fn sphl_load_sp_from_hl(&mut self) -> u32 {
         self.stack_pointer = self.u8_pair_to_u16(self.reg_l, self.reg_h);
+        self.shadow_stack.unwind(self.stack_pointer);
         self.program_counter += 1;
 
         return 5;
@@ -30,10 +31,12 @@ impl State {
 
     // XTHL
     pub fn xthl_exchange_top_stack_with_hl(&mut self) -> u32 {
-        let new_h = self.memory[(self.stack_pointer + 1) as usize];
-        let new_l = self.memory[self.stack_pointer as usize];
-        self.memory[(self.stack_pointer + 1) as usize] = self.reg_h;
-        self.memory[self.stack_pointer as usize] = self.reg_l;
+        let new_h = self.read_memory(self.stack_pointer + 1);
+        let new_l = self.read_memory(self.stack_pointer);
+        self.write_memory(self.stack_pointer + 1, self.reg_h);
+        self.write_memory(self.stack_pointer, self.reg_l);
+        let top = self.u8_pair_to_u16(self.reg_l, self.reg_h);
+        self.shadow_stack.rewrite(self.stack_pointer, top);
         self.reg_h = new_h;
         self.reg_l = new_l;
 
@@ -78,6 +81,7 @@ impl State {
             RegisterSymbols::PSW => self.set_psw_pair(result),
             _ => panic!("Invalid register given"),
         }
+        self.shadow_stack.unwind(self.stack_pointer);
 
         self.program_counter += 1;
 
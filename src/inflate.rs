@@ -0,0 +1,281 @@
+// raw deflate decompression (RFC 1951), enough for zipped ROM sets
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// order the code length code lengths are sent in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+// canonical Huffman code as the number of codes per length and symbols in code order
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+
+        Huffman { counts, symbols }
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+impl BitReader<'_> {
+    // deflate packs bits from the least significant end of each byte
+    fn bits(&mut self, count: u32) -> Result<u32, String> {
+        while self.bit_count < count {
+            let Some(byte) = self.data.get(self.position) else {
+                return Err("unexpected end of compressed data".to_string());
+            };
+            self.bit_buffer |= (*byte as u32) << self.bit_count;
+            self.position += 1;
+            self.bit_count += 8;
+        }
+
+        let value = self.bit_buffer & ((1 << count) - 1);
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+        return Ok(value);
+    }
+
+    fn decode(&mut self, huffman: &Huffman) -> Result<u16, String> {
+        // codes of each length follow on from the last code of the previous length
+        let mut code = 0;
+        let mut first = 0;
+        let mut index = 0;
+        for length in 1..16 {
+            code |= self.bits(1)? as i32;
+            let count = huffman.counts[length] as i32;
+            if code - count < first {
+                return Ok(huffman.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        return Err("invalid Huffman code".to_string());
+    }
+}
+
+fn inflate_stored(input: &mut BitReader, output: &mut Vec<u8>) -> Result<(), String> {
+    // stored blocks start on a byte boundary
+    input.bit_buffer = 0;
+    input.bit_count = 0;
+
+    let start = input.position;
+    let Some(header) = input.data.get(start..start + 4) else {
+        return Err("unexpected end of compressed data".to_string());
+    };
+    let length = u16::from_le_bytes([header[0], header[1]]);
+    if length != !u16::from_le_bytes([header[2], header[3]]) {
+        return Err("stored block length doesn't match its complement".to_string());
+    }
+    let Some(bytes) = input.data.get(start + 4..start + 4 + length as usize) else {
+        return Err("unexpected end of compressed data".to_string());
+    };
+    output.extend_from_slice(bytes);
+    input.position = start + 4 + length as usize;
+
+    Ok(())
+}
+
+fn inflate_codes(
+    input: &mut BitReader,
+    output: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), String> {
+    loop {
+        let symbol = input.decode(literals)? as usize;
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let symbol = symbol - 257;
+                if symbol >= LENGTH_BASE.len() {
+                    return Err("invalid length code".to_string());
+                }
+                let length = LENGTH_BASE[symbol] as usize
+                    + input.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+
+                let symbol = input.decode(distances)? as usize;
+                if symbol >= DISTANCE_BASE.len() {
+                    return Err("invalid distance code".to_string());
+                }
+                let distance = DISTANCE_BASE[symbol] as usize
+                    + input.bits(DISTANCE_EXTRA[symbol] as u32)? as usize;
+                if distance > output.len() {
+                    return Err("distance reaches before the start of the data".to_string());
+                }
+
+                // the copy may overlap what it is writing
+                let start = output.len() - distance;
+                for ind in 0..length {
+                    output.push(output[start + ind]);
+                }
+            }
+        }
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0; 288];
+    lengths[0..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..288].fill(8);
+
+    return (Huffman::new(&lengths), Huffman::new(&[5; 30]));
+}
+
+fn dynamic_codes(input: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let literal_count = input.bits(5)? as usize + 257;
+    let distance_count = input.bits(5)? as usize + 1;
+    let code_length_count = input.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0; 19];
+    for ind in 0..code_length_count {
+        code_lengths[CODE_LENGTH_ORDER[ind]] = input.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (length, repeat) = match input.decode(&code_lengths)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => match lengths.last() {
+                Some(previous) => (*previous, 3 + input.bits(2)?),
+                None => return Err("repeated length with no previous length".to_string()),
+            },
+            17 => (0, 3 + input.bits(3)?),
+            _ => (0, 11 + input.bits(7)?),
+        };
+        for _ in 0..repeat {
+            lengths.push(length);
+        }
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err("code lengths run past the end of the table".to_string());
+    }
+
+    return Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ));
+}
+
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut input = BitReader {
+        data,
+        position: 0,
+        bit_buffer: 0,
+        bit_count: 0,
+    };
+    let mut output = Vec::new();
+
+    loop {
+        let last = input.bits(1)? == 1;
+        match input.bits(2)? {
+            0 => inflate_stored(&mut input, &mut output)?,
+            1 => {
+                let (literals, distances) = fixed_codes();
+                inflate_codes(&mut input, &mut output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut input)?;
+                inflate_codes(&mut input, &mut output, &literals, &distances)?;
+            }
+            _ => return Err("invalid block type".to_string()),
+        }
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // raw deflate streams from zlib with a window of -15
+
+    #[test]
+    fn stored_block() {
+        let data = [
+            0x01, 0x0c, 0x00, 0xf3, 0xff, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x2c, 0x20, 0x77, 0x6f,
+            0x72, 0x6c, 0x64,
+        ];
+        assert_eq!(inflate(&data).unwrap(), b"hello, world");
+    }
+
+    #[test]
+    fn fixed_block() {
+        let data = [0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x00];
+        assert_eq!(inflate(&data).unwrap(), b"hello hello hello");
+    }
+
+    #[test]
+    fn dynamic_block() {
+        let data = [
+            0xcd, 0x8e, 0x31, 0x0a, 0x80, 0x30, 0x10, 0x04, 0xbf, 0xb2, 0x75, 0x2a, 0xa3, 0x75,
+            0x90, 0x24, 0x1e, 0x18, 0x88, 0x97, 0x90, 0x3b, 0x85, 0xfc, 0xff, 0x23, 0x5a, 0x69,
+            0x6b, 0x61, 0x61, 0x37, 0xcb, 0xc0, 0x32, 0x89, 0x85, 0x9a, 0x02, 0xb1, 0x24, 0x06,
+            0xd2, 0xeb, 0x55, 0x77, 0x59, 0x51, 0x38, 0x77, 0x58, 0xd4, 0xec, 0x3b, 0x35, 0x84,
+            0x5d, 0xb5, 0xf0, 0xc7, 0xc6, 0x48, 0x2c, 0x8d, 0xe0, 0x97, 0xc3, 0x73, 0x24, 0xa8,
+            0x0f, 0x99, 0x0c, 0xdc, 0x8c, 0xad, 0x8b, 0x52, 0xeb, 0x70, 0xd3, 0x80, 0x7a, 0x25,
+            0xa9, 0xc0, 0x8d, 0x0f, 0xda, 0x1b, 0xff, 0xf0, 0x70, 0x02,
+        ];
+        let expected = [
+            "INSERT  COIN  ".repeat(4),
+            "PUSH ONLY 1 PLAYER BUTTON ".repeat(4),
+            "*SCORE ADVANCE TABLE* =? MYSTERY =30 POINTS =20 POINTS =10 POINTS ".repeat(3),
+        ]
+        .concat();
+        assert_eq!((data[0] >> 1) & 3, 2);
+        assert_eq!(inflate(&data).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn truncated_stream_is_an_error() {
+        let data = [0xcb, 0x48, 0xcd, 0xc9];
+        assert!(inflate(&data).is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use crate::machine::InputBit;
+
+// keys the window keeps for stepping, the DIP switches, screenshots, save states and
+// other debugging, named as they are bound
+pub const RESERVED_KEYS: [&str; 18] = [
+    "u", "p", "n", "m", ",", ".", "h", "r", "x", "o", "b", "F1", "F2", "F3", "F4", "F5", "F8",
+    "F12",
+];
+
+// keys bound to the machine's controls and which of them are held,
+// the input ports are rebuilt from this every frame
+#[derive(Clone)]
+pub struct InputMap {
+    // control name and every key bound to it
+    bindings: Vec<(&'static str, Vec<String>)>,
+    held_keys: Vec<String>,
+    // controls held without a key, by headless runs
+    held_controls: Vec<&'static str>,
+}
+
+impl InputMap {
+    // the keys the machine driver suggests
+    pub fn new(inputs: &[InputBit]) -> InputMap {
+        InputMap {
+            bindings: inputs
+                .iter()
+                .map(|input| {
+                    let keys = input.keys.iter().map(|key| key.to_string()).collect();
+                    (input.name, keys)
+                })
+                .collect(),
+            held_keys: Vec::new(),
+            held_controls: Vec::new(),
+        }
+    }
+
+    fn find(&self, control: &str) -> Result<usize, String> {
+        match self.bindings.iter().position(|(name, _)| *name == control) {
+            Some(ind) => Ok(ind),
+            None => {
+                let names: Vec<&str> = self.bindings.iter().map(|(name, _)| *name).collect();
+                Err(format!(
+                    "no control {}, expected one of {}",
+                    control,
+                    names.join(", ")
+                ))
+            }
+        }
+    }
+
+    // replace the keys of a control, a key drives one control so it leaves any other
+    pub fn bind(&mut self, control: &str, keys: &[String]) -> Result<(), String> {
+        let ind = self.find(control)?;
+        if let Some(key) = keys
+            .iter()
+            .find(|key| RESERVED_KEYS.contains(&key.as_str()))
+        {
+            return Err(format!("{} is reserved by the emulator", key));
+        }
+        for (_, bound) in self.bindings.iter_mut() {
+            bound.retain(|key| !keys.contains(key));
+        }
+        self.bindings[ind].1 = keys.to_vec();
+
+        Ok(())
+    }
+
+    pub fn key(&mut self, key: &str, pressed: bool) {
+        self.held_keys.retain(|held| held != key);
+        if pressed {
+            self.held_keys.push(key.to_string());
+        }
+    }
+
+    pub fn set_control(&mut self, control: &str, pressed: bool) -> Result<(), String> {
+        let name = self.bindings[self.find(control)?].0;
+        self.held_controls.retain(|held| *held != name);
+        if pressed {
+            self.held_controls.push(name);
+        }
+
+        Ok(())
+    }
+
+    // held by a control of its own or through any of its keys
+    pub fn is_held(&self, control: &str) -> bool {
+        self.held_controls.contains(&control)
+            || self.bindings.iter().any(|(name, keys)| {
+                *name == control && keys.iter().any(|key| self.held_keys.contains(key))
+            })
+    }
+
+    pub fn print(&self) {
+        for (name, keys) in self.bindings.iter() {
+            println!("{:<12}{}", name, keys.join(", "));
+        }
+    }
+}
@@ -0,0 +1,38 @@
+// the emulator as a library: the 8080 core, the machine drivers and a gym style Environment
+// for agents to play through, forking State to run many at once. main.rs adds the window
+// and the command line on top
+#[cfg(target_os = "linux")]
+pub mod alsa;
+pub mod audio;
+pub mod callstack;
+pub mod checksum;
+pub mod config;
+pub mod dip_switches;
+pub mod disassemble;
+pub mod emulate8080;
+pub mod environment;
+pub mod hexfile;
+pub mod i8080;
+pub mod inflate;
+pub mod input;
+pub mod machine;
+pub mod mixer;
+pub mod overlay;
+pub mod patch;
+pub mod persistence;
+pub mod ppm;
+pub mod profiler;
+pub mod romdb;
+pub mod romset;
+pub mod script;
+pub mod shift_register;
+pub mod sound;
+pub mod synth;
+pub mod trace;
+pub mod video;
+pub mod wav;
+pub mod xref;
+pub mod zip;
+
+pub use environment::{Action, Environment, ACTIONS};
+pub use i8080::State;
@@ -0,0 +1,479 @@
+use crate::i8080::MemoryMap;
+use crate::persistence::MemoryRegion;
+
+// what sits on an I/O port of the board
+#[derive(Clone, Copy, PartialEq)]
+pub enum PortDevice {
+    // IN, one of the in_ports
+    Inputs(usize),
+    // IN, MB14241 shifted result
+    ShiftResult,
+    // OUT, MB14241
+    ShiftOffset,
+    ShiftData,
+    // OUT, Space Invaders sound latches
+    InvadersSound1,
+    InvadersSound2,
+    Watchdog,
+    // OUT, hardware that isn't emulated
+    Unconnected,
+}
+
+// one chip of the ROM set, checksums are left out where no verified dump is known
+#[derive(Clone)]
+pub struct RomChip {
+    pub name: &'static str,
+    pub address: u16,
+    pub size: usize,
+    pub crc32: Option<u32>,
+    pub sha1: Option<&'static str>,
+}
+
+// a control wired to port bits, keys use winit names like "a" or "ArrowLeft"
+#[derive(Clone)]
+pub struct InputBit {
+    pub name: &'static str,
+    // bound until the settings say otherwise
+    pub keys: Vec<&'static str>,
+    pub port: usize,
+    pub bits: u8,
+    pub active_low: bool,
+    // upright cabinets wire their one control panel to player 2's bits on this port too
+    pub upright_port: Option<usize>,
+}
+
+// where a game keeps player 1's progress, for scoring agents
+#[derive(Clone)]
+pub struct GameRam {
+    // two BCD bytes, low first
+    pub score: u16,
+    pub lives: u16,
+    // non-zero during a game rather than the attract mode
+    pub playing: u16,
+    // inputs from power on until a one player game is under way
+    pub start_script: &'static str,
+}
+
+#[derive(Clone)]
+pub struct DipSetting {
+    pub name: &'static str,
+    pub port: usize,
+    pub mask: u8,
+    // value name and the bits it sets, the first is the default
+    pub options: Vec<(&'static str, u8)>,
+}
+
+// everything that differs between games on the Midway/Taito 8080 board
+#[derive(Clone)]
+pub struct MachineDriver {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub roms: Vec<RomChip>,
+    pub memory_map: MemoryMap,
+    pub read_ports: Vec<(u8, PortDevice)>,
+    pub write_ports: Vec<(u8, PortDevice)>,
+    // in_ports with nothing pressed
+    pub port_defaults: [u8; 4],
+    pub inputs: Vec<InputBit>,
+    pub dip_switches: Vec<DipSetting>,
+    pub video_ram: usize,
+    // monitor mounted on its side, video lines run bottom to top
+    pub rotated: bool,
+    pub persistent_regions: Vec<MemoryRegion>,
+    pub game_ram: Option<GameRam>,
+}
+
+fn input(name: &'static str, key: &'static str, port: usize, bits: u8) -> InputBit {
+    InputBit {
+        name,
+        keys: vec![key],
+        port,
+        bits,
+        active_low: false,
+        upright_port: None,
+    }
+}
+
+fn chips(names: &[&'static str], addresses: &[u16], size: usize) -> Vec<RomChip> {
+    names
+        .iter()
+        .zip(addresses)
+        .map(|(name, address)| RomChip {
+            name,
+            address: *address,
+            size,
+            crc32: None,
+            sha1: None,
+        })
+        .collect()
+}
+
+fn verified_chip(
+    name: &'static str,
+    address: u16,
+    size: usize,
+    crc32: u32,
+    sha1: &'static str,
+) -> RomChip {
+    RomChip {
+        name,
+        address,
+        size,
+        crc32: Some(crc32),
+        sha1: Some(sha1),
+    }
+}
+
+// ports shared by Space Invaders and the Taito games on its board
+fn invaders_ports() -> (Vec<(u8, PortDevice)>, Vec<(u8, PortDevice)>) {
+    (
+        vec![
+            (0, PortDevice::Inputs(0)),
+            (1, PortDevice::Inputs(1)),
+            (2, PortDevice::Inputs(2)),
+            (3, PortDevice::ShiftResult),
+        ],
+        vec![
+            (2, PortDevice::ShiftOffset),
+            (3, PortDevice::InvadersSound1),
+            (4, PortDevice::ShiftData),
+            (5, PortDevice::InvadersSound2),
+            (6, PortDevice::Watchdog),
+        ],
+    )
+}
+
+fn invaders_inputs() -> Vec<InputBit> {
+    let mut inputs = vec![
+        input("coin", "c", 1, 0b00000001),
+        input("p2start", "2", 1, 0b00000010),
+        input("p1start", "1", 1, 0b00000100),
+        input("tilt", "t", 2, 0b00000100),
+        input("p2fire", "ArrowUp", 2, 0b00010000),
+        input("p2left", "ArrowLeft", 2, 0b00100000),
+        input("p2right", "ArrowRight", 2, 0b01000000),
+    ];
+    for (name, key, bits) in [
+        ("p1fire", "w", 0b00010000),
+        ("p1left", "a", 0b00100000),
+        ("p1right", "d", 0b01000000),
+    ] {
+        inputs.push(InputBit {
+            upright_port: Some(2),
+            ..input(name, key, 1, bits)
+        });
+    }
+
+    return inputs;
+}
+
+fn lives_dip(lives: &[&'static str]) -> DipSetting {
+    DipSetting {
+        name: "lives",
+        port: 2,
+        mask: lives.len() as u8 - 1,
+        options: lives
+            .iter()
+            .enumerate()
+            .map(|(ind, value)| (*value, ind as u8))
+            .collect(),
+    }
+}
+
+fn coin_info_dip() -> DipSetting {
+    DipSetting {
+        name: "coin_info",
+        port: 2,
+        mask: 0b10000000,
+        options: vec![("on", 0), ("off", 0b10000000)],
+    }
+}
+
+// 8K ROM at 0x0000, 8K RAM at 0x2000 mirrored from 0x4000 upwards
+fn invaders_memory_map() -> MemoryMap {
+    MemoryMap::Board {
+        rom: vec![(0x0000, 0x1fff)],
+        ram_start: 0x2000,
+        ram_mask: 0x1fff,
+        color_ram: None,
+    }
+}
+
+// the Taito color boards add a second ROM bank at 0x4000 and color RAM at 0xc000
+fn taito_memory_map() -> MemoryMap {
+    MemoryMap::Board {
+        rom: vec![(0x0000, 0x1fff), (0x4000, 0x5fff)],
+        ram_start: 0x2000,
+        ram_mask: 0x1fff,
+        color_ram: Some(0xc000),
+    }
+}
+
+pub fn space_invaders() -> MachineDriver {
+    let (read_ports, write_ports) = invaders_ports();
+    MachineDriver {
+        name: "invaders",
+        description: "Space Invaders (Midway, 1978)",
+        roms: vec![
+            verified_chip(
+                "invaders.h",
+                0x0000,
+                0x800,
+                0x734f5ad8,
+                "ff6200af4c9110d8181249cbcef1a8a40fa40b7b",
+            ),
+            verified_chip(
+                "invaders.g",
+                0x0800,
+                0x800,
+                0x6bfaca4a,
+                "16f48649b531bdef8c2d1446c429b5f414524350",
+            ),
+            verified_chip(
+                "invaders.f",
+                0x1000,
+                0x800,
+                0x0ccead96,
+                "537aef03468f63c5b9e11dd61e253f7ae17d9743",
+            ),
+            verified_chip(
+                "invaders.e",
+                0x1800,
+                0x800,
+                0x14e538b0,
+                "1d6ca0c99f9df71e2990b610deb9d7da0125e2d8",
+            ),
+        ],
+        memory_map: invaders_memory_map(),
+        read_ports,
+        write_ports,
+        port_defaults: [0b00001110, 0b00001000, 0b00000000, 0],
+        inputs: invaders_inputs(),
+        dip_switches: vec![
+            lives_dip(&["3", "4", "5", "6"]),
+            DipSetting {
+                name: "bonus_life",
+                port: 2,
+                mask: 0b00001000,
+                options: vec![("1500", 0), ("1000", 0b00001000)],
+            },
+            coin_info_dip(),
+        ],
+        video_ram: 0x2400,
+        rotated: true,
+        persistent_regions: vec![MemoryRegion {
+            name: "hiscore".to_string(),
+            start: 0x20f4,
+            length: 2,
+        }],
+        game_ram: Some(GameRam {
+            score: 0x20f8,
+            lives: 0x21ff,
+            playing: 0x20ef,
+            start_script: "wait 120; press coin; wait 60; press p1start; wait until mem[0x20ef]==1",
+        }),
+    }
+}
+
+pub fn space_invaders_part2() -> MachineDriver {
+    MachineDriver {
+        name: "invadpt2",
+        description: "Space Invaders Part II (Taito, 1979)",
+        roms: chips(
+            &["pv01", "pv02", "pv03", "pv04", "pv05"],
+            &[0x0000, 0x0800, 0x1000, 0x1800, 0x4000],
+            0x800,
+        ),
+        memory_map: taito_memory_map(),
+        dip_switches: vec![lives_dip(&["3", "4"]), coin_info_dip()],
+        persistent_regions: Vec::new(),
+        game_ram: None,
+        ..space_invaders()
+    }
+}
+
+pub fn lunar_rescue() -> MachineDriver {
+    MachineDriver {
+        name: "lrescue",
+        description: "Lunar Rescue (Taito, 1979)",
+        roms: chips(
+            &[
+                "lrescue.1",
+                "lrescue.2",
+                "lrescue.3",
+                "lrescue.4",
+                "lrescue.5",
+                "lrescue.6",
+            ],
+            &[0x0000, 0x0800, 0x1000, 0x1800, 0x4000, 0x4800],
+            0x800,
+        ),
+        memory_map: taito_memory_map(),
+        dip_switches: vec![lives_dip(&["3", "4", "5", "6"]), coin_info_dip()],
+        persistent_regions: Vec::new(),
+        game_ram: None,
+        ..space_invaders()
+    }
+}
+
+pub fn balloon_bomber() -> MachineDriver {
+    MachineDriver {
+        name: "ballbomb",
+        description: "Balloon Bomber (Taito, 1980)",
+        roms: chips(
+            &["tn01", "tn02", "tn03", "tn04", "tn05-1"],
+            &[0x0000, 0x0800, 0x1000, 0x1800, 0x4000],
+            0x800,
+        ),
+        memory_map: taito_memory_map(),
+        dip_switches: vec![lives_dip(&["3", "4", "5", "6"]), coin_info_dip()],
+        persistent_regions: Vec::new(),
+        game_ram: None,
+        ..space_invaders()
+    }
+}
+
+// landscape monitor, active low controls and its own audio port
+pub fn gun_fight() -> MachineDriver {
+    let mut inputs = Vec::new();
+    for (port, names, keys) in [
+        (
+            0,
+            [
+                "p1up",
+                "p1down",
+                "p1left",
+                "p1right",
+                "p1gunup",
+                "p1gundown",
+                "p1fire",
+            ],
+            ["w", "s", "a", "d", "q", "e", "f"],
+        ),
+        (
+            1,
+            [
+                "p2up",
+                "p2down",
+                "p2left",
+                "p2right",
+                "p2gunup",
+                "p2gundown",
+                "p2fire",
+            ],
+            [
+                "ArrowUp",
+                "ArrowDown",
+                "ArrowLeft",
+                "ArrowRight",
+                "PageUp",
+                "PageDown",
+                "Enter",
+            ],
+        ),
+    ] {
+        for ind in 0..7 {
+            // stick and gun from bit 0, fire on bit 7
+            let bits = if ind == 6 { 0b10000000 } else { 1 << ind };
+            inputs.push(InputBit {
+                active_low: true,
+                ..input(names[ind], keys[ind], port, bits)
+            });
+        }
+    }
+    inputs.push(input("coin", "c", 2, 0b01000000));
+
+    MachineDriver {
+        name: "gunfight",
+        description: "Gun Fight (Midway, 1975)",
+        roms: chips(
+            &["7609h.bin", "7609g.bin", "7609f.bin", "7609e.bin"],
+            &[0x0000, 0x0400, 0x0800, 0x0c00],
+            0x400,
+        ),
+        memory_map: invaders_memory_map(),
+        read_ports: vec![
+            (0, PortDevice::Inputs(0)),
+            (1, PortDevice::Inputs(1)),
+            (2, PortDevice::Inputs(2)),
+            (3, PortDevice::ShiftResult),
+        ],
+        write_ports: vec![
+            (1, PortDevice::Unconnected),
+            (2, PortDevice::ShiftOffset),
+            (4, PortDevice::ShiftData),
+        ],
+        port_defaults: [0xff, 0xff, 0b00000000, 0],
+        inputs,
+        dip_switches: vec![DipSetting {
+            name: "game_time",
+            port: 2,
+            mask: 0b00001100,
+            options: vec![
+                ("60", 0b00000000),
+                ("70", 0b00000100),
+                ("80", 0b00001000),
+                ("90", 0b00001100),
+            ],
+        }],
+        video_ram: 0x2400,
+        rotated: false,
+        persistent_regions: Vec::new(),
+        game_ram: None,
+    }
+}
+
+pub fn drivers() -> Vec<MachineDriver> {
+    vec![
+        space_invaders(),
+        space_invaders_part2(),
+        lunar_rescue(),
+        balloon_bomber(),
+        gun_fight(),
+    ]
+}
+
+pub fn find_driver(name: &str) -> Option<MachineDriver> {
+    drivers().into_iter().find(|driver| driver.name == name)
+}
+
+impl MachineDriver {
+    pub fn read_device(&self, port: u8) -> Option<PortDevice> {
+        self.read_ports
+            .iter()
+            .find(|(number, _)| *number == port)
+            .map(|(_, device)| *device)
+    }
+
+    pub fn write_device(&self, port: u8) -> Option<PortDevice> {
+        self.write_ports
+            .iter()
+            .find(|(number, _)| *number == port)
+            .map(|(_, device)| *device)
+    }
+
+    pub fn rom_size(&self) -> usize {
+        self.roms.iter().map(|chip| chip.size).sum()
+    }
+
+    // a single dump of the whole set, split across the chips in order, an image longer
+    // than the set isn't one so it loads flat from address 0
+    pub fn load_rom_image(&self, image: &[u8]) -> Vec<u8> {
+        let mut memory = vec![0; 0x10000];
+        if image.len() > self.rom_size() {
+            let length = image.len().min(memory.len());
+            memory[..length].copy_from_slice(&image[..length]);
+            return memory;
+        }
+
+        let mut offset = 0;
+        for chip in self.roms.iter() {
+            let length = chip.size.min(image.len().saturating_sub(offset));
+            let address = chip.address as usize;
+            memory[address..address + length].copy_from_slice(&image[offset..offset + length]);
+            offset += length;
+        }
+
+        return memory;
+    }
+}
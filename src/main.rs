@@ -1,11 +1,10 @@
-mod disassemble;
-mod emulate8080;
-mod i8080;
 mod shaders;
 
 use std::env;
 use std::fs;
 use std::io;
+use std::path::Path;
+use std::process;
 use std::sync::{Arc, Mutex};
 use std::thread;
 
@@ -60,8 +59,12 @@ use winit::{
     window::WindowBuilder,
 };
 
-use crate::emulate8080::copy_screen_memory;
-use crate::emulate8080::run_emulation;
+use i8080_emulator::emulate8080::copy_screen_memory;
+use i8080_emulator::emulate8080::run_emulation;
+use i8080_emulator::{
+    audio, config, dip_switches, disassemble, emulate8080, environment, hexfile, i8080, machine,
+    mixer, overlay, patch, persistence, profiler, romdb, romset, script, sound, synth, trace, xref,
+};
 
 #[derive(BufferContents, Vertex)]
 #[repr(C)]
@@ -98,6 +101,22 @@ fn adjust_window_size(
         .collect::<Vec<_>>()
 }
 
+fn export_memory(state: &i8080::State, (start, end): (u16, u16), filename: &str) {
+    match state.export_memory(start, end, filename) {
+        Ok(()) => println!("Exported {:04x}-{:04x} to {}", start, end, filename),
+        Err(why) => println!("Failed to export memory: {}", why),
+    }
+}
+
+// name a machine binds a control to, like "a" or "ArrowLeft"
+fn key_name(key: winit::keyboard::Key<&str>) -> Option<String> {
+    match key {
+        winit::keyboard::Key::Character(character) => Some(character.to_string()),
+        winit::keyboard::Key::Named(named) => Some(format!("{:?}", named)),
+        _ => None,
+    }
+}
+
 fn select_physical_device(
     instance: &Arc<Instance>,
     surface: &Arc<Surface>,
@@ -127,13 +146,127 @@ fn select_physical_device(
         .expect("No suitable device available")
 }
 
+// settings from the config file as flags, for the game the command line names
+fn config_args(args: &[String]) -> Vec<String> {
+    let flag_value = |names: &[&str]| {
+        args.windows(2)
+            .rev()
+            .find(|pair| names.contains(&pair[0].as_str()))
+            .map(|pair| pair[1].clone())
+    };
+    let config = match flag_value(&["--config"]) {
+        Some(filename) => config::Config::load(&filename),
+        None => match config::Config::default_path() {
+            Some(path) if path.exists() => config::Config::load(&path.to_string_lossy()),
+            _ => return Vec::new(),
+        },
+    };
+    let config = match config {
+        Ok(res) => res,
+        Err(why) => {
+            eprintln!("Failed to read config {}", why);
+            process::exit(1);
+        }
+    };
+
+    // per game sections are named for the ROM or the machine
+    let mut games = Vec::new();
+    if let Some(filename) = flag_value(&["-f", "--file"]) {
+        if let Some(stem) = Path::new(&filename).file_stem() {
+            games.push(stem.to_string_lossy().to_string());
+        }
+    }
+    if let Some(machine) = flag_value(&["--machine"]).or(config.machine()) {
+        games.push(machine);
+    }
+
+    match config.to_args(&games) {
+        Ok(res) => res,
+        Err(why) => {
+            eprintln!("Failed to read config {}", why);
+            process::exit(1);
+        }
+    }
+}
+
+// the image's chips at their load addresses, with the patched bytes following them
+fn place_rom_image(
+    machine: &machine::MachineDriver,
+    image: &[u8],
+    patched: &[bool],
+) -> (Vec<u8>, Vec<bool>) {
+    if image.len() > machine.rom_size() {
+        println!(
+            "Warning: image is {} bytes, longer than the {} bytes of {} chips, loading it flat",
+            image.len(),
+            machine.rom_size(),
+            machine.name
+        );
+    }
+    let mask: Vec<u8> = patched.iter().map(|patched| *patched as u8).collect();
+    let patched = machine
+        .load_rom_image(&mask)
+        .iter()
+        .map(|patched| *patched != 0)
+        .collect();
+
+    return (machine.load_rom_image(image), patched);
+}
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    // config file settings go first so the command line overrides them
+    let mut args: Vec<String> = env::args().collect();
+    let settings = config_args(&args);
+    // arguments before this came from the config
+    let command_line_start = settings.len() + 1;
+    args.splice(1..1, settings);
     let mut filename = String::new();
     let mut arg_iterator = 1;
     let mut do_test = false;
     let mut do_help = false;
     let mut do_dissassemble = false;
+    let mut do_xref = false;
+    let mut call_graph_filename = String::new();
+    let mut cfg_directory = String::new();
+    let mut do_profile = false;
+    let mut call_profile_filename = String::new();
+    let mut breakpoints = Vec::new();
+    let mut rom_write_policy = i8080::RomWritePolicy::Ignore;
+    let mut machine_name = String::from("invaders");
+    let mut machine_given = false;
+    let mut rom_db_filename = String::new();
+    let mut key_bindings = Vec::new();
+    let mut clock_speed = emulate8080::CYCLES_PER_SECOND;
+    let mut window_title = String::from("I8080 Emulator");
+    let mut window_scale = 0;
+    let mut do_list_machines = false;
+    let mut dip_filename = String::new();
+    let mut dip_settings = Vec::new();
+    let mut patch_filenames = Vec::new();
+    let mut patches_given = false;
+    let mut overlay_filename = String::new();
+    let mut overlay_settings = Vec::new();
+    let mut screenshot_filename = String::new();
+    let mut snapshot_filename = String::from("snapshot.sav");
+    let mut cocktail = false;
+    let mut nvram_directory = String::new();
+    let mut script_filename = String::new();
+    let mut gym_episodes = 0;
+    let mut frame_skip = 4;
+    let mut downsample = 1;
+    let mut two_player = false;
+    let mut headless_frames = 0;
+    let mut wav_filename = String::new();
+    let mut sample_directory = String::from("samples");
+    let mut synthesize_sound = false;
+    let mut audio_device = String::from("default");
+    let mut volume = 100;
+    let mut trace_filename = String::new();
+    let mut trace_format = trace::TraceFormat::Text;
+    let mut trace_range = (0x0000, 0xffff);
+    let mut export_range = (0x0000, 0xffff);
+    let mut export_filename = String::new();
+    let mut trace_cycles = (0, u64::MAX);
 
     // Get flags
     while arg_iterator < args.len() {
@@ -144,6 +277,207 @@ fn main() {
                 filename = args[arg_iterator].clone();
             }
             "-t" | "--test" => do_test = true,
+            "-x" | "--xref" => do_xref = true,
+            "--profile" => do_profile = true,
+            "--break" => {
+                arg_iterator += 1;
+                match u16::from_str_radix(args[arg_iterator].trim_start_matches("0x"), 16) {
+                    Ok(address) => breakpoints.push(address),
+                    Err(_) => panic!("Invalid breakpoint address {}", args[arg_iterator]),
+                }
+            }
+            "--rom-writes" => {
+                arg_iterator += 1;
+                rom_write_policy = match args[arg_iterator].as_str() {
+                    "ignore" => i8080::RomWritePolicy::Ignore,
+                    "log" => i8080::RomWritePolicy::Log,
+                    "trap" => i8080::RomWritePolicy::Trap,
+                    _ => panic!("Unknown ROM write policy {}", args[arg_iterator]),
+                };
+            }
+            "--lives" | "--bonus-life" | "--coin-info" => {
+                let name = args[arg_iterator]
+                    .trim_start_matches("--")
+                    .replace('-', "_");
+                arg_iterator += 1;
+                dip_settings.push((name, args[arg_iterator].clone()));
+            }
+            "--machine" => {
+                arg_iterator += 1;
+                machine_name = args[arg_iterator].clone();
+                machine_given = true;
+            }
+            "--list-machines" => do_list_machines = true,
+            "--rom-db" => {
+                arg_iterator += 1;
+                rom_db_filename = args[arg_iterator].clone();
+            }
+            // read before the other flags
+            "--config" => arg_iterator += 1,
+            "--bind" => {
+                arg_iterator += 1;
+                match args[arg_iterator].split_once('=') {
+                    Some((name, keys)) => {
+                        let keys: Vec<String> =
+                            keys.split(',').map(|key| key.to_string()).collect();
+                        key_bindings.push((name.to_string(), keys));
+                    }
+                    None => panic!("Expected --bind control=keys, got {}", args[arg_iterator]),
+                }
+            }
+            "--clock" => {
+                arg_iterator += 1;
+                clock_speed = match args[arg_iterator].parse() {
+                    Ok(clock) if clock > 0.0 => clock,
+                    _ => panic!("Clock must be in Hz, got {}", args[arg_iterator]),
+                };
+            }
+            "--title" => {
+                arg_iterator += 1;
+                window_title = args[arg_iterator].clone();
+            }
+            "--scale" => {
+                arg_iterator += 1;
+                window_scale = match args[arg_iterator].parse() {
+                    Ok(scale @ 1..=16) => scale,
+                    _ => panic!("Scale must be 1-16, got {}", args[arg_iterator]),
+                };
+            }
+            "--dip" => {
+                arg_iterator += 1;
+                match args[arg_iterator].split_once('=') {
+                    Some((name, value)) => dip_settings.push((name.to_string(), value.to_string())),
+                    None => panic!("Expected --dip name=value, got {}", args[arg_iterator]),
+                }
+            }
+            "--patch" => {
+                // patches on the command line replace the config's list
+                if arg_iterator >= command_line_start && !patches_given {
+                    patch_filenames.clear();
+                    patches_given = true;
+                }
+                arg_iterator += 1;
+                patch_filenames.push(args[arg_iterator].clone());
+            }
+            "--dip-file" => {
+                arg_iterator += 1;
+                dip_filename = args[arg_iterator].clone();
+            }
+            "--overlay" | "--phosphor" | "--background" => {
+                let name = args[arg_iterator].trim_start_matches("--").to_string();
+                arg_iterator += 1;
+                overlay_settings.push((name, args[arg_iterator].clone()));
+            }
+            "--overlay-file" => {
+                arg_iterator += 1;
+                overlay_filename = args[arg_iterator].clone();
+            }
+            "--cocktail" => cocktail = true,
+            "--no-cocktail" => cocktail = false,
+            "--nvram" => {
+                arg_iterator += 1;
+                nvram_directory = args[arg_iterator].clone();
+            }
+            "--two-player" => two_player = true,
+            "--script" => {
+                arg_iterator += 1;
+                script_filename = args[arg_iterator].clone();
+            }
+            "--gym" => {
+                arg_iterator += 1;
+                gym_episodes = match args[arg_iterator].parse() {
+                    Ok(res) => res,
+                    Err(why) => panic!("Failed to read episode count {}", why),
+                };
+            }
+            "--frame-skip" => {
+                arg_iterator += 1;
+                frame_skip = match args[arg_iterator].parse() {
+                    Ok(res) => res,
+                    Err(why) => panic!("Failed to read frame skip {}", why),
+                };
+            }
+            "--downsample" => {
+                arg_iterator += 1;
+                downsample = match args[arg_iterator].parse() {
+                    Ok(res) => res,
+                    Err(why) => panic!("Failed to read downsample {}", why),
+                };
+            }
+            "--screenshot" => {
+                arg_iterator += 1;
+                screenshot_filename = args[arg_iterator].clone();
+            }
+            "--snapshot" => {
+                arg_iterator += 1;
+                snapshot_filename = args[arg_iterator].clone();
+            }
+            "--headless" => {
+                arg_iterator += 1;
+                headless_frames = match args[arg_iterator].parse() {
+                    Ok(frames) => frames,
+                    Err(_) => panic!("Invalid frame count {}", args[arg_iterator]),
+                };
+            }
+            "--wav" => {
+                arg_iterator += 1;
+                wav_filename = args[arg_iterator].clone();
+            }
+            "--samples" => {
+                arg_iterator += 1;
+                sample_directory = args[arg_iterator].clone();
+            }
+            "--synth" => synthesize_sound = true,
+            "--audio-device" => {
+                arg_iterator += 1;
+                audio_device = args[arg_iterator].clone();
+            }
+            "--volume" => {
+                arg_iterator += 1;
+                volume = match args[arg_iterator].parse() {
+                    Ok(volume @ 0..=100) => volume,
+                    _ => panic!("Volume must be 0-100, got {}", args[arg_iterator]),
+                };
+            }
+            "--callprofile" => {
+                arg_iterator += 1;
+                call_profile_filename = args[arg_iterator].clone();
+            }
+            "--callgraph" => {
+                arg_iterator += 1;
+                call_graph_filename = args[arg_iterator].clone();
+            }
+            "--cfg" => {
+                arg_iterator += 1;
+                cfg_directory = args[arg_iterator].clone();
+            }
+            "--trace" => {
+                arg_iterator += 1;
+                trace_filename = args[arg_iterator].clone();
+                trace_format = trace::TraceFormat::Text;
+            }
+            "--trace-binary" => {
+                arg_iterator += 1;
+                trace_filename = args[arg_iterator].clone();
+                trace_format = trace::TraceFormat::Binary;
+            }
+            "--export" => {
+                export_range = trace::parse_address_range(&args[arg_iterator + 1]);
+                export_filename = args[arg_iterator + 2].clone();
+                arg_iterator += 2;
+            }
+            "--trace-range" => {
+                arg_iterator += 1;
+                trace_range = trace::parse_address_range(&args[arg_iterator]);
+            }
+            "--trace-cycles" => {
+                arg_iterator += 1;
+                trace_cycles = trace::parse_cycle_window(&args[arg_iterator]);
+            }
+            "--trace-diff" => {
+                trace::find_divergence(&args[arg_iterator + 1], &args[arg_iterator + 2]);
+                return;
+            }
             "-h" | "--help" => do_help = true,
             _ => panic!("Unknown flag given {}", args[arg_iterator]),
         }
@@ -155,12 +489,93 @@ fn main() {
         println!("8080 Emulator");
         println!("flags                 input               description");
         println!("-d, --disassemble                         Disassemble file");
-        println!("-f, --file            <filename>          Enter filename");
+        println!(
+            "-f, --file            <filename>          ROM image, or a ROM set directory or zip"
+        );
         println!("-t, --test                                Indicates test file");
+        println!("-x, --xref                                Print cross-reference table");
+        println!("    --callgraph       <filename>          Write call graph as DOT");
+        println!("    --cfg             <directory>         Write control-flow graphs as DOT");
+        println!("    --break           <address>           Start stepping at address (hex)");
+        println!(
+            "    --patch           <file.ips|file.bps> Patch the ROM image, repeat to apply in order"
+        );
+        println!("    --rom-writes      <ignore|log|trap>   Handling of writes to ROM");
+        println!("    --machine         <name>              Game board to emulate (invaders)");
+        println!("    --list-machines                       List supported games");
+        println!("    --rom-db          <file>              More known images and bad dumps");
+        println!("    --dip             <name>=<value>      Set a DIP switch of the machine");
+        println!(
+            "    --bind            <control>=<keys>    Bind a control to keys, comma separated"
+        );
+        println!("    --config          <filename>          Settings file replacing the user's");
+        println!("    --clock           <hz>                CPU clock speed (1996800)");
+        println!("    --title           <title>             Window title");
+        println!(
+            "    --scale           <1-16>              Window size as a multiple of the screen"
+        );
+        println!("    --lives           <3-6>               Lives per game DIP switch");
+        println!("    --bonus-life      <1000|1500>         Extra life score DIP switch");
+        println!("    --coin-info       <on|off>            Coin info display DIP switch");
+        println!("    --dip-file        <filename>          Read DIP switches from file");
+        println!("    --overlay         <classic|none>      Colored cellophane overlay");
+        println!(
+            "    --phosphor        <preset|rrggbb>     Monitor color (white, green, amber, blue)"
+        );
+        println!("    --background      <filename>          Background image (binary PPM)");
+        println!("    --overlay-file    <filename>          Read overlay bands and colors");
+        println!("    --nvram           <directory>         Keep the high score between sessions");
+        println!("    --cocktail                            Cocktail table, flip for player 2");
+        println!("    --no-cocktail                         Upright cabinet, the default");
+        println!("    --two-player                          Headless 2 player game from coin up");
+        println!("    --script          <filename>          Play inputs from a script");
+        println!("    --gym             <episodes>          Random agent rollouts, one per thread");
+        println!("    --frame-skip      <frames>            Frames per agent step (4)");
+        println!(
+            "    --downsample      <pixels>            Screen pixels per observation cell (1)"
+        );
+        println!("    --screenshot      <filename>          Screenshot file (F12, headless end)");
+        println!("    --snapshot        <filename>          Save state file (F5 save, F8 load)");
+        println!("    --headless        <frames>            Run without a window for frames");
+        println!("    --wav             <filename>          Record sound to a WAV file");
+        println!("    --samples         <directory>         Sound samples 0.wav-9.wav");
+        println!("    --synth                               Model the sound board, no samples");
+        println!("    --audio-device    <name|none>         ALSA audio output (default)");
+        println!("    --volume          <0-100>             Audio output volume");
+        println!("    --profile                             Profile execution (o to print)");
+        println!("    --callprofile     <filename>          Write per-function folded stacks");
+        println!("    --trace           <filename>          Log every instruction as text");
+        println!("    --trace-binary    <filename>          Log every instruction as binary");
+        println!("    --trace-range     <start-end>         Only trace PCs in range (hex)");
+        println!(
+            "    --export          <start-end> <file>  Save memory as .hex, .s19 or raw ('x')"
+        );
+        println!("    --trace-cycles    <start-end>         Only trace within cycle window");
+        println!("    --trace-diff      <first> <second>    Find first divergence of traces");
         println!("-h, --help                                print command info");
         return;
     }
 
+    if do_list_machines {
+        for driver in machine::drivers() {
+            println!("{:<10} {}", driver.name, driver.description);
+            let roms: Vec<&str> = driver.roms.iter().map(|chip| chip.name).collect();
+            println!("           roms: {}", roms.join(" "));
+            let controls: Vec<String> = driver
+                .inputs
+                .iter()
+                .map(|input| format!("{}={}", input.name, input.keys.join(",")))
+                .collect();
+            println!("           controls: {}", controls.join(" "));
+        }
+        return;
+    }
+
+    let mut machine = match machine::find_driver(&machine_name) {
+        Some(res) => res,
+        None => panic!("Unknown machine {} (use --list-machines)", machine_name),
+    };
+
     // Get filename if it wasn't set in the flags
     if filename == "" {
         println!("File not provided (use -h or --help for flags)");
@@ -171,33 +586,258 @@ fn main() {
         filename = filename.trim().to_string();
     }
 
-    // Read the filename to a buffer
-    let mut buffer: Vec<u8> = match fs::read(filename.clone()) {
-        Ok(res) => res,
-        Err(why) => panic!("Failed to open file {}: {}", filename, why),
+    // Read the filename to a buffer, a directory or zip is the machine's ROM set with its chips
+    // one after another like a single file, hex files come back already placed in 64K
+    let rom_set = romset::is_rom_set(&filename);
+    let hex_file = hexfile::HexFormat::from_filename(&filename) != hexfile::HexFormat::Binary;
+    let mut start_address = None;
+    let mut rom_db = romdb::RomDb::new();
+    if rom_db_filename != "" {
+        if let Err(why) = rom_db.load(&rom_db_filename) {
+            eprintln!("Failed to read ROM database {}", why);
+            process::exit(1);
+        }
+    }
+    // a set is recognised by its chips' names, their contents are checked as it loads
+    if rom_set && !machine_given {
+        match romset::find_machine(&filename) {
+            Ok(Some(driver)) => {
+                println!("Identified {}", driver.description);
+                machine = driver;
+            }
+            Ok(None) => (),
+            Err(why) => {
+                eprintln!("Failed to read ROM set {}: {}", filename, why);
+                process::exit(1);
+            }
+        }
+    }
+    let mut buffer: Vec<u8> = if rom_set {
+        match romset::load_rom_set(&filename, &machine, &rom_db) {
+            Ok(res) => res,
+            Err(why) => {
+                eprintln!(
+                    "Failed to load {} ROM set {}:\n{}",
+                    machine.name, filename, why
+                );
+                process::exit(1);
+            }
+        }
+    } else if hex_file {
+        match hexfile::read(&filename) {
+            Ok(res) => {
+                start_address = res.start;
+                res.memory
+            }
+            Err(why) => {
+                eprintln!("Failed to load {}", why);
+                process::exit(1);
+            }
+        }
+    } else {
+        match fs::read(filename.clone()) {
+            Ok(res) => res,
+            Err(why) => {
+                eprintln!("Failed to open file {}: {}", filename, why);
+                process::exit(1);
+            }
+        }
     };
+    let placed = rom_set || hex_file;
+    let mut monitor = false;
+
+    // known images pick their machine, or test mode for CP/M programs, unless the flags did
+    if !placed && !machine_given && !do_test {
+        match rom_db.identify(&buffer) {
+            Some(identification) => {
+                println!("Identified {}", identification.description);
+                for chip in identification.bad_chips.iter() {
+                    println!("Warning: {} is a known bad dump", chip);
+                }
+                for chip in identification.unknown_chips.iter() {
+                    println!("Warning: {} doesn't match any known dump", chip);
+                }
+                match identification.kind {
+                    romdb::RomKind::Arcade(name) => machine = machine::find_driver(name).unwrap(),
+                    romdb::RomKind::Cpm => do_test = true,
+                    romdb::RomKind::Monitor => monitor = true,
+                }
+            }
+            None if filename.to_ascii_lowercase().ends_with(".com") => {
+                println!("Unknown CP/M program, running in test mode");
+                do_test = true;
+            }
+            None => (),
+        }
+    }
+
+    // patches apply in order to the image as dumped, offsets counting from the start of the
+    // first chip, hex files have no such image so their offsets are CPU addresses
+    let mut patched = Vec::new();
+    for patch_filename in patch_filenames.iter() {
+        if let Err(why) = patch::apply(patch_filename, &mut buffer, &mut patched) {
+            eprintln!("Failed to apply patch {}", why);
+            process::exit(1);
+        }
+        let count = patched.iter().filter(|patched| **patched).count();
+        println!("Applied {}, {} bytes patched", patch_filename, count);
+    }
+
+    // a set is placed once patched, so analysis sees its chips at their addresses
+    if rom_set {
+        (buffer, patched) = place_rom_image(&machine, &buffer, &patched);
+    }
 
     // Disassemble provided file
     if do_dissassemble {
         let mut program_counter = 0;
         while program_counter < buffer.len() {
-            program_counter += disassemble::disassemble8080_op(&buffer, program_counter);
+            program_counter += disassemble::disassemble8080_op(&buffer, program_counter, &patched);
+        }
+        return;
+    }
+
+    // Analyze provided file
+    if do_xref || call_graph_filename != "" || cfg_directory != "" {
+        let analysis = xref::Analysis::new(&buffer);
+        if do_xref {
+            analysis.print_xref_table();
+        }
+        if call_graph_filename != "" {
+            if let Err(why) = analysis.write_call_graph(&call_graph_filename) {
+                panic!("Failed to write {}: {}", call_graph_filename, why);
+            }
+        }
+        if cfg_directory != "" {
+            if let Err(why) = analysis.write_control_flow_graphs(&cfg_directory) {
+                panic!("Failed to write {}: {}", cfg_directory, why);
+            }
         }
         return;
     }
 
     // 8080 memory size is 2^64
-    buffer.resize(0x10000, 0);
+    if !placed && do_test {
+        // CP/M programs load after the zero page
+        buffer.splice(0..0, vec![0; romdb::CPM_LOAD_ADDRESS]);
+        buffer.resize(0x10000, 0);
+        patched.splice(0..0, vec![false; romdb::CPM_LOAD_ADDRESS]);
+    } else if !placed && monitor {
+        buffer.resize(0x10000, 0);
+        patched.resize(0x10000, false);
+    } else if !placed {
+        (buffer, patched) = place_rom_image(&machine, &buffer, &patched);
+    }
+    patched.resize(0x10000, false);
 
     let state = Arc::new(Mutex::new(i8080::State::new(buffer, do_test)));
 
+    // Command line DIP switches override the file
+    let mut dip_switches = dip_switches::DipSwitches::new(machine.dip_switches.clone());
+    if dip_filename != "" {
+        if let Err(why) = dip_switches.load(&dip_filename) {
+            panic!("Failed to read DIP switches {}", why);
+        }
+    }
+    for (name, value) in dip_settings {
+        if let Err(why) = dip_switches.set(&name, &value) {
+            panic!("Invalid DIP switch: {}", why);
+        }
+    }
+
+    // Command line overlay settings override the file
+    let mut overlay = overlay::Overlay::new();
+    overlay.rotated = machine.rotated;
+    if overlay_filename != "" {
+        if let Err(why) = overlay.load(&overlay_filename) {
+            panic!("Failed to read overlay {}", why);
+        }
+    }
+    for (name, value) in overlay_settings {
+        if let Err(why) = overlay.set(&name, &value) {
+            panic!("Invalid overlay setting: {}", why);
+        }
+    }
+
+    {
+        let mut state = state.lock().unwrap();
+        state.set_machine(machine.clone());
+        for (name, keys) in key_bindings {
+            if let Err(why) = state.input_map.bind(&name, &keys) {
+                panic!("Invalid key binding for {}: {}", machine.name, why);
+            }
+        }
+        state.breakpoints = breakpoints;
+        state.patched = patched;
+        state.rom_write_policy = rom_write_policy;
+        state.dip_switches = dip_switches;
+        state.overlay = overlay;
+        state.cocktail = cocktail;
+        state.clock_speed = clock_speed;
+        state.reset();
+        if let Some(start) = start_address {
+            state.program_counter = start;
+        }
+    }
+
+    // two coins then 2 player start, for alternating games without a keyboard
+    let two_player_script = "wait 60; press coin; wait 20; press coin; wait 20; press p2start";
+    let controls: Vec<&str> = machine.inputs.iter().map(|input| input.name).collect();
+    let script = if script_filename != "" {
+        Some(script::Script::load(&script_filename, &controls))
+    } else if two_player {
+        Some(script::Script::parse(
+            "--two-player",
+            two_player_script,
+            &controls,
+        ))
+    } else {
+        None
+    };
+    match script {
+        Some(Ok(res)) => state.lock().unwrap().script = Some(res),
+        Some(Err(why)) => panic!("Failed to read script {}", why),
+        None => (),
+    }
+
+    if do_profile {
+        state.lock().unwrap().profiler = Some(profiler::Profiler::new());
+    }
+
+    if call_profile_filename != "" {
+        state.lock().unwrap().call_profiler =
+            Some(profiler::CallProfiler::new(&call_profile_filename));
+    }
+
+    if nvram_directory != "" && !machine.persistent_regions.is_empty() {
+        if let Err(why) = fs::create_dir_all(&nvram_directory) {
+            panic!("Failed to create directory {}: {}", nvram_directory, why);
+        }
+        let rom_name = Path::new(&filename).file_stem().unwrap().to_string_lossy();
+        let nvram_path = Path::new(&nvram_directory).join(format!("{}.nv", rom_name));
+        match persistence::Persistence::new(
+            &nvram_path.to_string_lossy(),
+            machine.persistent_regions.clone(),
+        ) {
+            Ok(res) => state.lock().unwrap().persistence = Some(res),
+            Err(why) => panic!("Failed to read persistent memory {}", why),
+        }
+    }
+
+    if trace_filename != "" {
+        let mut tracer = match trace::Tracer::new(&trace_filename, trace_format) {
+            Ok(res) => res,
+            Err(why) => panic!("Failed to open file {}: {}", trace_filename, why),
+        };
+        tracer.address_range = trace_range;
+        tracer.cycle_window = trace_cycles;
+        state.lock().unwrap().tracer = Some(tracer);
+    }
+
     {
         let mut state = state.lock().unwrap();
         // Test files don't need vulkan
         if state.testing {
-            // provided test needs to start at 0x100
-            state.memory.rotate_right(0x100);
-
             // jump to 0x100
             state.memory[0x00] = 0xc3;
             state.memory[0x01] = 0x00;
@@ -213,7 +853,7 @@ fn main() {
             while !state.should_exit {
                 state.check_and_print_call();
                 let prev_pc = state.program_counter;
-                emulate8080::emulate8080_op(&mut state);
+                emulate8080::profile8080_op(&mut state);
                 if state.program_counter == 0 {
                     println!("Exit from {:04x}", prev_pc);
                     state.should_exit = true;
@@ -221,6 +861,72 @@ fn main() {
             }
             return;
         }
+
+        // Agent rollouts run without a window or sound
+        if gym_episodes > 0 {
+            match environment::Environment::new(&state, frame_skip, downsample) {
+                Ok(res) => environment::run_random_rollouts(&res, gym_episodes),
+                Err(why) => {
+                    eprintln!("Failed to start the environment: {}", why);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+
+        // a WAV file replaces the device, headless runs only play into a file
+        let sink = if wav_filename != "" {
+            Some(audio::AudioSink::File {
+                filename: wav_filename.clone(),
+                samples: Vec::new(),
+            })
+        } else if headless_frames > 0 {
+            None
+        } else if audio_device == "none" {
+            Some(audio::AudioSink::Null)
+        } else {
+            match audio::AudioSink::open_device(&audio_device) {
+                Ok(sink) => Some(sink),
+                Err(why) => {
+                    println!("No audio device ({}), sound disabled", why);
+                    Some(audio::AudioSink::Null)
+                }
+            }
+        };
+
+        if let Some(sink) = sink {
+            state.audio = Some(audio::AudioOutput::new(sink, volume as f64 / 100.0));
+            state.sound_board = Some(if synthesize_sound {
+                sound::SoundBoard::Synth(synth::SoundSynth::new(clock_speed))
+            } else {
+                sound::SoundBoard::Samples(mixer::SampleMixer::new(&sample_directory, clock_speed))
+            });
+        }
+
+        // Headless runs don't need vulkan either
+        if headless_frames > 0 {
+            emulate8080::run_headless(&mut state, headless_frames);
+            if export_filename != "" {
+                export_memory(&state, export_range, &export_filename);
+            }
+            if screenshot_filename != "" {
+                if let Err(why) = state.overlay.write_screenshot(
+                    &state.video,
+                    state.screen_flipped(),
+                    &screenshot_filename,
+                ) {
+                    println!("Failed to write screenshot: {}", why);
+                }
+            }
+            if !state.script.as_ref().map_or(true, |script| script.passed()) {
+                process::exit(1);
+            }
+            return;
+        }
+    }
+
+    if screenshot_filename == "" {
+        screenshot_filename = String::from("screenshot.ppm");
     }
 
     let event_loop = EventLoop::new().unwrap();
@@ -237,12 +943,15 @@ fn main() {
     )
     .expect("Failed to create instance");
 
-    let window = Arc::new(
-        WindowBuilder::new()
-            .with_title("I8080 Emulator")
-            .build(&event_loop)
-            .unwrap(),
-    );
+    let mut window_builder = WindowBuilder::new().with_title(window_title);
+    if window_scale > 0 {
+        let (width, height) = state.lock().unwrap().overlay.size();
+        window_builder = window_builder.with_inner_size(winit::dpi::LogicalSize::new(
+            (width * window_scale) as u32,
+            (height * window_scale) as u32,
+        ));
+    }
+    let window = Arc::new(window_builder.build(&event_loop).unwrap());
 
     let surface = Surface::from_window(instance.clone(), window.clone()).unwrap();
 
@@ -308,22 +1017,27 @@ fn main() {
 
     let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
 
+    // texture rows are video lines, turned to columns on a rotated monitor
+    let tex_coords = match machine.rotated {
+        true => [[1.0, 0.0], [0.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+        false => [[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]],
+    };
     let vertices = [
         Vertex2D {
             position: [-1.0, -1.0],
-            tex_coords: [1.0, 0.0],
+            tex_coords: tex_coords[0],
         },
         Vertex2D {
             position: [-1.0, 1.0],
-            tex_coords: [0.0, 0.0],
+            tex_coords: tex_coords[1],
         },
         Vertex2D {
             position: [1.0, -1.0],
-            tex_coords: [1.0, 1.0],
+            tex_coords: tex_coords[2],
         },
         Vertex2D {
             position: [1.0, 1.0],
-            tex_coords: [0.0, 1.0],
+            tex_coords: tex_coords[3],
         },
     ];
 
@@ -592,130 +1306,99 @@ fn main() {
 
                                 state.call_interrupt(1);
                             }
-                            winit::keyboard::Key::Character("b") => {
-                                let state = state.lock().unwrap();
-
-                                println!("--------------------------------------------------");
-                                println!("| A|F |  | B|C |  | D|E |  | H|L |  | PC |  | SP |");
-                                println!("|{:02x}|{:02x}|  |{:02x}|{:02x}|  |{:02x}|{:02x}|  |{:02x}|{:02x}|  |{:04x}|  |{:04x}|", state.reg_a, state.flags_to_u8(), state.reg_b, state.reg_c, state.reg_d, state.reg_e, state.reg_h, state.reg_l, state.program_counter, state.stack_pointer);
-                                println!("--------------------------------------------------");
-                            }
-                            // Game inputs
-                            winit::keyboard::Key::Character("c") => {
+                            winit::keyboard::Key::Character("r") => {
                                 let mut state = state.lock().unwrap();
 
-                                // deposit credit
-                                state.in_ports[1] |= 0b00000001;
+                                println!("Resetting");
+                                state.dip_switches.print();
+                                state.reset();
                             }
-                            winit::keyboard::Key::Character("1") => {
+                            // DIP switches, most games only read them after a reset
+                            winit::keyboard::Key::Named(winit::keyboard::NamedKey::F1) => {
                                 let mut state = state.lock().unwrap();
 
-                                // 1 player start
-                                state.in_ports[1] |= 0b00000100;
+                                state.dip_switches.cycle(0);
+                                state.dip_switches.print();
                             }
-                            winit::keyboard::Key::Character("2") => {
+                            winit::keyboard::Key::Named(winit::keyboard::NamedKey::F2) => {
                                 let mut state = state.lock().unwrap();
 
-                                // 2 player start
-                                state.in_ports[1] |= 0b00000010;
+                                state.dip_switches.cycle(1);
+                                state.dip_switches.print();
                             }
-                            winit::keyboard::Key::Character("w") => {
+                            winit::keyboard::Key::Named(winit::keyboard::NamedKey::F3) => {
                                 let mut state = state.lock().unwrap();
 
-                                // player 1 shoot
-                                state.in_ports[1] |= 0b00010000;
+                                state.dip_switches.cycle(2);
+                                state.dip_switches.print();
                             }
-                            winit::keyboard::Key::Character("a") => {
-                                let mut state = state.lock().unwrap();
-
-                                // player 1 left
-                                state.in_ports[1] |= 0b00100000;
+                            winit::keyboard::Key::Named(winit::keyboard::NamedKey::F4) => {
+                                state.lock().unwrap().input_map.print();
                             }
-                            winit::keyboard::Key::Character("d") => {
-                                let mut state = state.lock().unwrap();
+                            winit::keyboard::Key::Named(winit::keyboard::NamedKey::F12) => {
+                                let state = state.lock().unwrap();
 
-                                // player 1 right
-                                state.in_ports[1] |= 0b01000000;
+                                match state.overlay.write_screenshot(&state.video, state.screen_flipped(), &screenshot_filename) {
+                                    Ok(()) => println!("Saved screenshot to {}", screenshot_filename),
+                                    Err(why) => println!("Failed to write screenshot: {}", why),
+                                }
                             }
-                            winit::keyboard::Key::Named(winit::keyboard::NamedKey::ArrowUp) => {
-                                let mut state = state.lock().unwrap();
+                            winit::keyboard::Key::Named(winit::keyboard::NamedKey::F5) => {
+                                let snapshot = state.lock().unwrap().save_snapshot();
 
-                                // player 2 shoot
-                                state.in_ports[2] |= 0b00010000;
+                                match fs::write(&snapshot_filename, snapshot) {
+                                    Ok(()) => println!("Saved state to {}", snapshot_filename),
+                                    Err(why) => println!("Failed to write {}: {}", snapshot_filename, why),
+                                }
                             }
-                            winit::keyboard::Key::Named(winit::keyboard::NamedKey::ArrowLeft) => {
-                                let mut state = state.lock().unwrap();
+                            winit::keyboard::Key::Named(winit::keyboard::NamedKey::F8) => {
+                                let result = fs::read(&snapshot_filename)
+                                    .map_err(|why| why.to_string())
+                                    .and_then(|snapshot| state.lock().unwrap().load_snapshot(&snapshot));
+
+                                match result {
+                                    Ok(()) => println!("Loaded state from {}", snapshot_filename),
+                                    Err(why) => println!("Failed to load {}: {}", snapshot_filename, why),
+                                }
+                            }
+                            winit::keyboard::Key::Character("x") => {
+                                if export_filename != "" {
+                                    export_memory(&state.lock().unwrap(), export_range, &export_filename);
+                                }
+                            }
+                            winit::keyboard::Key::Character("o") => {
+                                let state = state.lock().unwrap();
 
-                                // player 2 left
-                                state.in_ports[2] |= 0b00100000;
+                                if let Some(profiler) = &state.profiler {
+                                    profiler.print_report(&state.memory);
+                                }
                             }
-                            winit::keyboard::Key::Named(winit::keyboard::NamedKey::ArrowRight) => {
-                                let mut state = state.lock().unwrap();
+                            winit::keyboard::Key::Character("b") => {
+                                let state = state.lock().unwrap();
 
-                                // player 2 right
-                                state.in_ports[2] |= 0b01000000;
+                                println!("--------------------------------------------------");
+                                println!("| A|F |  | B|C |  | D|E |  | H|L |  | PC |  | SP |");
+                                println!("|{:02x}|{:02x}|  |{:02x}|{:02x}|  |{:02x}|{:02x}|  |{:02x}|{:02x}|  |{:04x}|  |{:04x}|", state.reg_a, state.flags_to_u8(), state.reg_b, state.reg_c, state.reg_d, state.reg_e, state.reg_h, state.reg_l, state.program_counter, state.stack_pointer);
+                                println!("--------------------------------------------------");
+                                println!("Scanline {}", state.video.scanline(state.cycle_count));
+                                state.shadow_stack.print_backtrace(&state.memory, state.program_counter);
+                            }
+                            // Game inputs come from the machine's key bindings
+                            key => {
+                                if let Some(name) = key_name(key) {
+                                    state.lock().unwrap().input_map.key(&name, true);
+                                }
                             }
-                            _ => (),
                         }
                     }
                     else if event.state == ElementState::Released && !event.repeat {
                         match event.key_without_modifiers().as_ref() {
-                            // Game inputs
-                            winit::keyboard::Key::Character("c") => {
-                                let mut state = state.lock().unwrap();
-
-                                // deposit credit
-                                state.in_ports[1] &= 0b11111110;
-                            }
-                            winit::keyboard::Key::Character("1") => {
-                                let mut state = state.lock().unwrap();
-
-                                // 1 player start
-                                state.in_ports[1] &= 0b11111011;
+                            // Game inputs come from the machine's key bindings
+                            key => {
+                                if let Some(name) = key_name(key) {
+                                    state.lock().unwrap().input_map.key(&name, false);
+                                }
                             }
-                            winit::keyboard::Key::Character("2") => {
-                                let mut state = state.lock().unwrap();
-
-                                // 2 player start
-                                state.in_ports[1] &= 0b11111101;
-                            }
-                            winit::keyboard::Key::Character("w") => {
-                                let mut state = state.lock().unwrap();
-
-                                // player 1 shoot
-                                state.in_ports[1] &= 0b11101111;
-                            }
-                            winit::keyboard::Key::Character("a") => {
-                                let mut state = state.lock().unwrap();
-
-                                // player 1 left
-                                state.in_ports[1] &= 0b11011111;
-                            }
-                            winit::keyboard::Key::Character("d") => {
-                                let mut state = state.lock().unwrap();
-
-                                // player 1 right
-                                state.in_ports[1] &= 0b10111111;
-                            }
-                            winit::keyboard::Key::Named(winit::keyboard::NamedKey::ArrowUp) => {
-                                let mut state = state.lock().unwrap();
-
-                                // player 2 shoot
-                                state.in_ports[2] &= 0b11101111;
-                            }
-                            winit::keyboard::Key::Named(winit::keyboard::NamedKey::ArrowLeft) => {
-                                let mut state = state.lock().unwrap();
-
-                                // player 2 left
-                                state.in_ports[2] &= 0b11011111;
-                            }
-                            winit::keyboard::Key::Named(winit::keyboard::NamedKey::ArrowRight) => {
-                                let mut state = state.lock().unwrap();
-
-                                // player 2 right
-                                state.in_ports[2] &= 0b10111111;
-                            }
-                            _ => (),
                         }
                     }
                 }
@@ -855,7 +1538,13 @@ fn main() {
                         }
                     }
                 }
-                Event::AboutToWait => window.request_redraw(),
+                Event::AboutToWait => {
+                    // a script can quit
+                    if state.lock().unwrap().should_exit {
+                        elwt.exit();
+                    }
+                    window.request_redraw();
+                }
                 _ => (),
             }
         })
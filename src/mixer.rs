@@ -0,0 +1,98 @@
+use std::path::Path;
+
+use crate::sound::SoundEvent;
+use crate::wav::Wave;
+
+pub const SAMPLE_RATE: u32 = 44100;
+const SAMPLE_COUNT: usize = 10;
+
+struct Voice {
+    sample: usize,
+    position: f64,
+    looping: bool,
+}
+
+// plays the numbered sample files for each sound event, rendered against the cycle count
+pub struct SampleMixer {
+    samples: Vec<Option<Wave>>,
+    voices: Vec<Voice>,
+    muted: bool,
+    cycles_per_sample: f64,
+    // samples rendered since start, output is drained by the audio sink
+    rendered: usize,
+    pub output: Vec<i16>,
+}
+
+impl SampleMixer {
+    pub fn new(directory: &str, cpu_clock: f64) -> SampleMixer {
+        let samples = (0..SAMPLE_COUNT)
+            .map(|number| {
+                let path = Path::new(directory).join(format!("{}.wav", number));
+                // a missing or unreadable sample stays silent, --synth covers a missing set
+                match Wave::read(&path.to_string_lossy()) {
+                    Ok(wave) if !wave.samples.is_empty() => Some(wave),
+                    _ => None,
+                }
+            })
+            .collect();
+
+        SampleMixer {
+            samples,
+            voices: Vec::new(),
+            muted: true,
+            cycles_per_sample: cpu_clock / SAMPLE_RATE as f64,
+            rendered: 0,
+            output: Vec::new(),
+        }
+    }
+
+    pub fn trigger(&mut self, event: SoundEvent, cycle: u64) {
+        self.advance(cycle);
+
+        match event {
+            SoundEvent::AmplifierOn => self.muted = false,
+            SoundEvent::AmplifierOff => self.muted = true,
+            SoundEvent::UfoStop => self.voices.retain(|voice| !voice.looping),
+            _ => {
+                let sample = event.sample_number().unwrap();
+                if self.samples[sample].is_none() {
+                    return;
+                }
+                // retriggering restarts a sound rather than layering it
+                self.voices.retain(|voice| voice.sample != sample);
+                self.voices.push(Voice {
+                    sample,
+                    position: 0.0,
+                    looping: event == SoundEvent::UfoStart,
+                });
+            }
+        }
+    }
+
+    // render output up to the given CPU cycle
+    pub fn advance(&mut self, cycle: u64) {
+        let target = (cycle as f64 / self.cycles_per_sample) as usize;
+        while self.rendered < target {
+            let mut mixed: i32 = 0;
+            for voice in self.voices.iter_mut() {
+                let wave = self.samples[voice.sample].as_ref().unwrap();
+                mixed += wave.samples[voice.position as usize] as i32;
+                voice.position += wave.sample_rate as f64 / SAMPLE_RATE as f64;
+                if voice.looping && voice.position >= wave.samples.len() as f64 {
+                    voice.position -= wave.samples.len() as f64;
+                }
+            }
+            let samples = &self.samples;
+            self.voices.retain(|voice| {
+                (voice.position as usize) < samples[voice.sample].as_ref().unwrap().samples.len()
+            });
+
+            if self.muted {
+                mixed = 0;
+            }
+            self.output
+                .push(mixed.clamp(i16::MIN as i32, i16::MAX as i32) as i16);
+            self.rendered += 1;
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use std::fs;
+use std::io;
+
+use crate::ppm::Image;
+use crate::video::{Video, BYTES_PER_LINE, VISIBLE_LINES};
+
+const LINE_PIXELS: usize = BYTES_PER_LINE * 8;
+
+// a strip of cellophane in screen pixels as the player sees them, end exclusive
+#[derive(Clone)]
+pub struct Band {
+    pub left: usize,
+    pub top: usize,
+    pub right: usize,
+    pub bottom: usize,
+    pub color: [u8; 3],
+}
+
+// colors the frame on the CPU so every output of it matches the window
+#[derive(Clone)]
+pub struct Overlay {
+    // later bands are on top
+    pub bands: Vec<Band>,
+    pub phosphor: [u8; 3],
+    pub background: Option<Image>,
+    // monitor mounted on its side so each video line is a column
+    pub rotated: bool,
+}
+
+fn parse_color(value: &str) -> Result<[u8; 3], String> {
+    match value {
+        "white" => return Ok([0xff, 0xff, 0xff]),
+        "red" => return Ok([0xff, 0x20, 0x20]),
+        "green" => return Ok([0x20, 0xff, 0x20]),
+        _ => (),
+    }
+    match u32::from_str_radix(value, 16) {
+        Ok(rgb) if value.len() == 6 => Ok([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8]),
+        _ => Err(format!(
+            "color must be rrggbb, white, red or green, got {}",
+            value
+        )),
+    }
+}
+
+// 3-bit cell color of the color boards, bit 0 red, bit 1 blue, bit 2 green
+fn cell_color(color: u8) -> [u8; 3] {
+    [0, 2, 1].map(|bit| ((color >> bit) & 1) * 0xff)
+}
+
+fn parse_band(value: &str, (width, height): (usize, usize)) -> Result<Band, String> {
+    let fields: Vec<&str> = value.split_whitespace().collect();
+    if fields.len() != 5 {
+        return Err("band must be left top right bottom color".to_string());
+    }
+    let mut bounds = [0; 4];
+    for (ind, field) in fields[0..4].iter().enumerate() {
+        bounds[ind] = match field.parse() {
+            Ok(res) => res,
+            Err(_) => return Err(format!("invalid band coordinate {}", field)),
+        };
+    }
+    let [left, top, right, bottom] = bounds;
+    if left >= right || top >= bottom || right > width || bottom > height {
+        return Err(format!(
+            "band must lie within {}x{} with left < right and top < bottom",
+            width, height
+        ));
+    }
+
+    Ok(Band {
+        left,
+        top,
+        right,
+        bottom,
+        color: parse_color(fields[4])?,
+    })
+}
+
+impl Overlay {
+    // plain white on black like an unmodified monitor
+    pub fn new() -> Overlay {
+        Overlay {
+            bands: Vec::new(),
+            phosphor: [0xff, 0xff, 0xff],
+            background: None,
+            rotated: true,
+        }
+    }
+
+    // red across the UFO row, green over the shields, player and lives
+    pub fn classic_bands() -> Vec<Band> {
+        let red = [0xff, 0x20, 0x20];
+        let green = [0x20, 0xff, 0x20];
+        vec![
+            Band {
+                left: 0,
+                top: 32,
+                right: 224,
+                bottom: 64,
+                color: red,
+            },
+            Band {
+                left: 0,
+                top: 184,
+                right: 224,
+                bottom: 240,
+                color: green,
+            },
+            Band {
+                left: 16,
+                top: 240,
+                right: 134,
+                bottom: 256,
+                color: green,
+            },
+        ]
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match (name, value) {
+            ("overlay", "classic") => self.bands = Overlay::classic_bands(),
+            ("overlay", "none") => self.bands.clear(),
+            ("overlay", _) => {
+                return Err(format!("overlay must be classic or none, got {}", value))
+            }
+            ("band", _) => self.bands.push(parse_band(value, self.size())?),
+            ("phosphor", "white") => self.phosphor = [0xff, 0xff, 0xff],
+            ("phosphor", "green") => self.phosphor = [0x33, 0xff, 0x66],
+            ("phosphor", "amber") => self.phosphor = [0xff, 0xb0, 0x00],
+            ("phosphor", "blue") => self.phosphor = [0xc0, 0xd8, 0xff],
+            ("phosphor", _) => match parse_color(value) {
+                Ok(color) => self.phosphor = color,
+                Err(_) => {
+                    return Err(format!(
+                        "phosphor must be white, green, amber, blue or rrggbb, got {}",
+                        value
+                    ))
+                }
+            },
+            ("background", _) => self.background = Some(Image::read(value)?),
+            _ => return Err(format!("unknown overlay setting {}", name)),
+        }
+
+        Ok(())
+    }
+
+    // "name = value" per line, # starts a comment, bands replace any preset
+    pub fn load(&mut self, filename: &str) -> Result<(), String> {
+        let contents = match fs::read_to_string(filename) {
+            Ok(res) => res,
+            Err(why) => return Err(format!("{}: {}", filename, why)),
+        };
+
+        let mut replaced_bands = false;
+        for (ind, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let result = match line.split_once('=') {
+                Some((name, value)) => {
+                    if name.trim() == "band" && !replaced_bands {
+                        self.bands.clear();
+                        replaced_bands = true;
+                    }
+                    self.set(name.trim(), value.trim())
+                }
+                None => Err("expected name = value".to_string()),
+            };
+            if let Err(why) = result {
+                return Err(format!("{}:{}: {}", filename, ind + 1, why));
+            }
+        }
+
+        Ok(())
+    }
+
+    // screen size as the player sees it
+    pub fn size(&self) -> (usize, usize) {
+        match self.rotated {
+            true => (VISIBLE_LINES, LINE_PIXELS),
+            false => (LINE_PIXELS, VISIBLE_LINES),
+        }
+    }
+
+    // video line and pixel drawn at a screen position
+    pub fn beam_position(&self, x: usize, y: usize) -> (usize, usize) {
+        match self.rotated {
+            // bit 0 of a line is the bottom of the screen
+            true => (x, LINE_PIXELS - 1 - y),
+            false => (y, x),
+        }
+    }
+
+    // the picture turns for a flipped screen, the cellophane stays put,
+    // color RAM takes the place of the cellophane on boards that have it
+    fn pixel(&self, video: &Video, flipped: bool, x: usize, y: usize) -> [u8; 3] {
+        let (width, height) = self.size();
+        let (mut line, mut bit) = self.beam_position(x, y);
+        if flipped {
+            line = VISIBLE_LINES - 1 - line;
+            bit = LINE_PIXELS - 1 - bit;
+        }
+        let byte = line * BYTES_PER_LINE + bit / 8;
+        if (video.frame[byte] >> (bit % 8)) & 1 == 0 {
+            return match &self.background {
+                Some(image) => image.sample(x, y, width, height),
+                None => [0, 0, 0],
+            };
+        }
+
+        let tint = match &video.colors {
+            Some(colors) => cell_color(colors[byte]),
+            None => self
+                .bands
+                .iter()
+                .rev()
+                .find(|band| {
+                    (band.left..band.right).contains(&x) && (band.top..band.bottom).contains(&y)
+                })
+                .map_or([0xff, 0xff, 0xff], |band| band.color),
+        };
+
+        return [0, 1, 2].map(|ind| (self.phosphor[ind] as u16 * tint[ind] as u16 / 0xff) as u8);
+    }
+
+    // RGBA in video memory order, as uploaded to the window texture
+    pub fn render(&self, video: &Video, flipped: bool, output: &mut [u8]) {
+        let (width, height) = self.size();
+        for x in 0..width {
+            for y in 0..height {
+                let [red, green, blue] = self.pixel(video, flipped, x, y);
+                let (line, bit) = self.beam_position(x, y);
+                let offset = (line * LINE_PIXELS + bit) * 4;
+                output[offset..offset + 4].copy_from_slice(&[red, green, blue, 0xff]);
+            }
+        }
+    }
+
+    // PPM of the frame as the player sees it
+    pub fn write_screenshot(&self, video: &Video, flipped: bool, filename: &str) -> io::Result<()> {
+        let (width, height) = self.size();
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(self.pixel(video, flipped, x, y));
+            }
+        }
+
+        Image {
+            width,
+            height,
+            pixels,
+        }
+        .write(filename)
+    }
+}
@@ -0,0 +1,336 @@
+use std::fs;
+
+use crate::checksum::crc32;
+
+// "PATCH", records of a 24 bit offset and 16 bit size, "EOF" and an optional truncation size,
+// a zero size is a run of one byte
+fn apply_ips(patch: &[u8], image: &mut Vec<u8>) -> Result<(), String> {
+    let truncated = |offset: usize| format!("truncated record at {:x}", offset);
+    let mut position = 5;
+    loop {
+        let Some(offset) = patch.get(position..position + 3) else {
+            return Err("missing EOF marker".to_string());
+        };
+        if offset == b"EOF" {
+            position += 3;
+            break;
+        }
+        let offset = u32::from_be_bytes([0, offset[0], offset[1], offset[2]]) as usize;
+        let Some(size) = patch.get(position + 3..position + 5) else {
+            return Err(truncated(position));
+        };
+        let size = u16::from_be_bytes([size[0], size[1]]) as usize;
+
+        let data = match size {
+            0 => {
+                let Some(run) = patch.get(position + 5..position + 8) else {
+                    return Err(truncated(position));
+                };
+                position += 8;
+                vec![run[2]; u16::from_be_bytes([run[0], run[1]]) as usize]
+            }
+            _ => {
+                let Some(data) = patch.get(position + 5..position + 5 + size) else {
+                    return Err(truncated(position));
+                };
+                position += 5 + size;
+                data.to_vec()
+            }
+        };
+        if image.len() < offset + data.len() {
+            image.resize(offset + data.len(), 0);
+        }
+        image[offset..offset + data.len()].copy_from_slice(&data);
+    }
+
+    if let Some(size) = patch.get(position..position + 3) {
+        image.truncate(u32::from_be_bytes([0, size[0], size[1], size[2]]) as usize);
+    }
+
+    Ok(())
+}
+
+struct PatchReader<'a> {
+    patch: &'a [u8],
+    position: usize,
+}
+
+impl PatchReader<'_> {
+    fn byte(&mut self) -> Result<u8, String> {
+        // the last 12 bytes are the checksums
+        if self.position >= self.patch.len() - 12 {
+            return Err("unexpected end of patch".to_string());
+        }
+        self.position += 1;
+        return Ok(self.patch[self.position - 1]);
+    }
+
+    // variable length number, each continuation also adds the next power of 128
+    fn number(&mut self) -> Result<usize, String> {
+        let mut value = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = self.byte()?;
+            value = value.saturating_add((byte & 0x7f) as usize * shift);
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.saturating_mul(128);
+            value = value.saturating_add(shift);
+        }
+    }
+
+    // copy offsets move relative to the last copy, the low bit is the sign
+    fn relative(&mut self, offset: &mut usize) -> Result<(), String> {
+        let value = self.number()?;
+        let distance = value >> 1;
+        *offset = match value & 1 {
+            1 => offset.checked_sub(distance),
+            _ => offset.checked_add(distance),
+        }
+        .ok_or_else(|| "copy offset out of range".to_string())?;
+
+        Ok(())
+    }
+}
+
+fn check_crc(name: &str, data: &[u8], footer: &[u8]) -> Result<(), String> {
+    let expected = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]);
+    let actual = crc32(data);
+    if actual != expected {
+        return Err(format!(
+            "{} CRC32 {:08x}, expected {:08x}",
+            name, actual, expected
+        ));
+    }
+
+    Ok(())
+}
+
+// "BPS1", sizes, metadata and copy actions, then the source, target and patch CRC32s
+fn apply_bps(patch: &[u8], image: &mut Vec<u8>) -> Result<(), String> {
+    if patch.len() < 4 + 12 {
+        return Err("unexpected end of patch".to_string());
+    }
+    let footer = &patch[patch.len() - 12..];
+    if let Err(why) = check_crc("patch", &patch[..patch.len() - 4], &footer[8..12]) {
+        return Err(format!("corrupt patch, {}", why));
+    }
+    if let Err(why) = check_crc("source", image, &footer[0..4]) {
+        return Err(format!("made for a different ROM, {}", why));
+    }
+
+    let mut reader = PatchReader { patch, position: 4 };
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    let metadata_size = reader.number()?;
+    reader.position += metadata_size;
+    if source_size != image.len() {
+        return Err(format!(
+            "made for a {} byte ROM, image is {} bytes",
+            source_size,
+            image.len()
+        ));
+    }
+
+    let source = std::mem::take(image);
+    let mut target = Vec::with_capacity(target_size);
+    let mut source_offset = 0;
+    let mut target_offset = 0;
+    while reader.position < patch.len() - 12 {
+        let action = reader.number()?;
+        let length = (action >> 2) + 1;
+        match action & 3 {
+            // source read, the same bytes as the source at this position
+            0 => match source.get(target.len()..target.len() + length) {
+                Some(bytes) => target.extend_from_slice(bytes),
+                None => return Err("source read past the end of the ROM".to_string()),
+            },
+            // target read, new bytes from the patch
+            1 => {
+                for _ in 0..length {
+                    target.push(reader.byte()?);
+                }
+            }
+            2 => {
+                reader.relative(&mut source_offset)?;
+                match source.get(source_offset..source_offset + length) {
+                    Some(bytes) => target.extend_from_slice(bytes),
+                    None => return Err("source copy past the end of the ROM".to_string()),
+                }
+                source_offset += length;
+            }
+            // target copy, may overlap what it is writing
+            _ => {
+                reader.relative(&mut target_offset)?;
+                for _ in 0..length {
+                    let Some(byte) = target.get(target_offset) else {
+                        return Err("target copy past the end of the output".to_string());
+                    };
+                    target.push(*byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_size {
+        return Err(format!(
+            "patch produced {} bytes, expected {}",
+            target.len(),
+            target_size
+        ));
+    }
+    check_crc("target", &target, &footer[4..8])?;
+    *image = target;
+
+    Ok(())
+}
+
+// applies an IPS or BPS patch to the image, marking the bytes it changed
+pub fn apply(filename: &str, image: &mut Vec<u8>, patched: &mut Vec<bool>) -> Result<(), String> {
+    let patch = match fs::read(filename) {
+        Ok(res) => res,
+        Err(why) => return Err(format!("{}: {}", filename, why)),
+    };
+
+    let original = image.clone();
+    let result = if patch.starts_with(b"PATCH") {
+        apply_ips(&patch, image)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(&patch, image)
+    } else {
+        Err("not an IPS or BPS patch".to_string())
+    };
+    if let Err(why) = result {
+        *image = original;
+        return Err(format!("{}: {}", filename, why));
+    }
+
+    patched.resize(image.len(), false);
+    for (ind, byte) in image.iter().enumerate() {
+        if original.get(ind) != Some(byte) {
+            patched[ind] = true;
+        }
+    }
+
+    Ok(())
+}
+
+// debugger annotation for an instruction containing patched bytes
+pub fn note(patched: &[bool], address: usize, length: usize) -> &'static str {
+    match patched
+        .iter()
+        .skip(address)
+        .take(length)
+        .any(|patched| *patched)
+    {
+        true => "  ; patched",
+        false => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bps_number(bytes: &mut Vec<u8>, mut value: usize) {
+        loop {
+            let low = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(0x80 | low);
+                return;
+            }
+            bytes.push(low);
+            value -= 1;
+        }
+    }
+
+    // actions as (action, length, relative offset or new bytes)
+    fn bps_patch(source: &[u8], target: &[u8], actions: &[(usize, usize, &[u8])]) -> Vec<u8> {
+        let mut patch = b"BPS1".to_vec();
+        bps_number(&mut patch, source.len());
+        bps_number(&mut patch, target.len());
+        bps_number(&mut patch, 0);
+        for (action, length, data) in actions {
+            bps_number(&mut patch, ((length - 1) << 2) | action);
+            match action {
+                1 => patch.extend_from_slice(data),
+                2 | 3 => bps_number(&mut patch, data[0] as usize),
+                _ => (),
+            }
+        }
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        let patch_crc = crc32(&patch);
+        patch.extend_from_slice(&patch_crc.to_le_bytes());
+
+        return patch;
+    }
+
+    #[test]
+    fn ips_records_runs_and_truncation() {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x02, 0x00, 0x02, 0xaa, 0xbb]);
+        patch.extend_from_slice(&[0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x03, 0xcc]);
+        patch.extend_from_slice(b"EOF");
+        let mut image = vec![0; 8];
+        apply_ips(&patch, &mut image).unwrap();
+        assert_eq!(image, [0, 0, 0xaa, 0xbb, 0, 0, 0xcc, 0xcc, 0xcc]);
+
+        patch.extend_from_slice(&[0x00, 0x00, 0x04]);
+        let mut image = vec![0; 8];
+        apply_ips(&patch, &mut image).unwrap();
+        assert_eq!(image, [0, 0, 0xaa, 0xbb]);
+    }
+
+    #[test]
+    fn ips_without_eof() {
+        let patch = b"PATCH\x00\x00\x00\x00\x01\xff";
+        assert!(apply_ips(patch, &mut vec![0; 4]).is_err());
+    }
+
+    #[test]
+    fn bps_round_trip() {
+        let source = b"8080 emulator".to_vec();
+        let target = b"8080 8080 emulated".to_vec();
+        let patch = bps_patch(
+            &source,
+            &target,
+            &[
+                (0, 5, &[]),
+                // the first 5 bytes again, then 6 from source offset 5, the low bit is the sign
+                (3, 5, &[0]),
+                (2, 6, &[10]),
+                (1, 2, b"ed"),
+            ],
+        );
+        let mut image = source.clone();
+        apply_bps(&patch, &mut image).unwrap();
+        assert_eq!(image, target);
+    }
+
+    #[test]
+    fn bps_checksums() {
+        let source = b"abcd".to_vec();
+        let target = b"abXd".to_vec();
+        let patch = bps_patch(&source, &target, &[(0, 2, &[]), (1, 1, b"X"), (0, 1, &[])]);
+
+        let mut other = b"abce".to_vec();
+        let why = apply_bps(&patch, &mut other).unwrap_err();
+        assert!(why.starts_with("made for a different ROM"), "{}", why);
+
+        let mut corrupt = patch.clone();
+        corrupt[8] ^= 1;
+        let why = apply_bps(&corrupt, &mut source.clone()).unwrap_err();
+        assert!(why.starts_with("corrupt patch"), "{}", why);
+
+        let mut wrong_target = patch[..patch.len() - 4].to_vec();
+        wrong_target[patch.len() - 8] ^= 1;
+        let patch_crc = crc32(&wrong_target);
+        wrong_target.extend_from_slice(&patch_crc.to_le_bytes());
+        let why = apply_bps(&wrong_target, &mut source.clone()).unwrap_err();
+        assert!(why.starts_with("target CRC32"), "{}", why);
+    }
+}
@@ -0,0 +1,136 @@
+use std::fs;
+use std::io;
+
+use crate::i8080::State;
+
+// RAM a game keeps between sessions, like a high score table
+#[derive(Clone)]
+pub struct MemoryRegion {
+    pub name: String,
+    pub start: u16,
+    pub length: u16,
+}
+
+// saves regions on exit and restores them once the game has initialized them itself,
+// so its own start up code doesn't overwrite the restored values
+pub struct Persistence {
+    filename: String,
+    regions: Vec<MemoryRegion>,
+    // bytes to restore for each region, from the file or the last reset
+    saved: Vec<Option<Vec<u8>>>,
+    // bytes of each region the game has written since reset
+    written: Vec<Vec<bool>>,
+    restored: Vec<bool>,
+}
+
+impl Persistence {
+    // "name address bytes" per line in hex, a missing file starts fresh
+    pub fn new(filename: &str, regions: Vec<MemoryRegion>) -> Result<Persistence, String> {
+        let mut saved = vec![None; regions.len()];
+        if let Ok(contents) = fs::read_to_string(filename) {
+            for (ind, line) in contents.lines().enumerate() {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let [name, start, bytes] = fields[..] else {
+                    return Err(format!(
+                        "{}:{}: expected name address bytes",
+                        filename,
+                        ind + 1
+                    ));
+                };
+                let bytes: Option<Vec<u8>> = match bytes.len() % 2 {
+                    0 => (0..bytes.len() / 2)
+                        .map(|byte| u8::from_str_radix(bytes.get(byte * 2..byte * 2 + 2)?, 16).ok())
+                        .collect(),
+                    _ => None,
+                };
+                let region = regions.iter().position(|region| {
+                    region.name == name && format!("{:04x}", region.start) == start
+                });
+                match (region, bytes) {
+                    (Some(region), Some(bytes))
+                        if bytes.len() == regions[region].length as usize =>
+                    {
+                        saved[region] = Some(bytes)
+                    }
+                    (Some(_), _) => {
+                        return Err(format!(
+                            "{}:{}: invalid bytes for {}",
+                            filename,
+                            ind + 1,
+                            name
+                        ))
+                    }
+                    // regions the game no longer declares are dropped
+                    (None, _) => (),
+                }
+            }
+        }
+
+        Ok(Persistence {
+            filename: filename.to_string(),
+            written: regions
+                .iter()
+                .map(|region| vec![false; region.length as usize])
+                .collect(),
+            restored: vec![false; regions.len()],
+            regions,
+            saved,
+        })
+    }
+
+    // wait for the game to initialize every region again, keeping what it had reached
+    pub fn reset(&mut self, state: &State) {
+        for ind in 0..self.regions.len() {
+            if self.restored[ind] {
+                self.saved[ind] = Some(self.read_region(state, ind));
+            }
+            self.written[ind].fill(false);
+            self.restored[ind] = false;
+        }
+    }
+
+    fn read_region(&self, state: &State, ind: usize) -> Vec<u8> {
+        let region = &self.regions[ind];
+        (0..region.length)
+            .map(|offset| state.read_memory(region.start.wrapping_add(offset)))
+            .collect()
+    }
+
+    // called for every CPU write, returns the bytes to restore once a region is fully written
+    pub fn record_write(&mut self, address: u16) -> Option<(u16, Vec<u8>)> {
+        for (ind, region) in self.regions.iter().enumerate() {
+            let offset = address.wrapping_sub(region.start);
+            if self.restored[ind] || offset >= region.length {
+                continue;
+            }
+
+            self.written[ind][offset as usize] = true;
+            if self.written[ind].iter().all(|written| *written) {
+                self.restored[ind] = true;
+                if let Some(bytes) = &self.saved[ind] {
+                    return Some((region.start, bytes.clone()));
+                }
+            }
+        }
+
+        return None;
+    }
+
+    // regions the game never initialized keep their previous contents
+    pub fn save(&self, state: &State) -> io::Result<()> {
+        let mut contents = String::new();
+        for (ind, region) in self.regions.iter().enumerate() {
+            let bytes = match self.restored[ind] {
+                true => self.read_region(state, ind),
+                false => match &self.saved[ind] {
+                    Some(bytes) => bytes.clone(),
+                    None => continue,
+                },
+            };
+            let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            contents += &format!("{} {:04x} {}\n", region.name, region.start, hex);
+        }
+
+        fs::write(&self.filename, contents)
+    }
+}
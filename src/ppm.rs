@@ -0,0 +1,92 @@
+use std::fs;
+use std::io;
+
+// 8-bit RGB image, rows top to bottom
+#[derive(Clone)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl Image {
+    // binary PPM (P6) with a maximum value of 255
+    pub fn read(filename: &str) -> Result<Image, String> {
+        let bytes = match fs::read(filename) {
+            Ok(res) => res,
+            Err(why) => return Err(format!("{}: {}", filename, why)),
+        };
+
+        // magic, width, height and maximum value separated by whitespace and # comments
+        let mut fields = Vec::new();
+        let mut offset = 0;
+        while fields.len() < 4 {
+            while offset < bytes.len() && bytes[offset].is_ascii_whitespace() {
+                offset += 1;
+            }
+            if offset < bytes.len() && bytes[offset] == b'#' {
+                while offset < bytes.len() && bytes[offset] != b'\n' {
+                    offset += 1;
+                }
+                continue;
+            }
+            let start = offset;
+            while offset < bytes.len() && !bytes[offset].is_ascii_whitespace() {
+                offset += 1;
+            }
+            if start == offset {
+                return Err(format!("{}: truncated header", filename));
+            }
+            fields.push(String::from_utf8_lossy(&bytes[start..offset]).to_string());
+        }
+        // single whitespace byte before the pixel data
+        offset += 1;
+
+        if fields[0] != "P6" {
+            return Err(format!("{}: only binary PPM (P6) is supported", filename));
+        }
+        let (Ok(width), Ok(height), Ok(255)) = (
+            fields[1].parse::<usize>(),
+            fields[2].parse::<usize>(),
+            fields[3].parse::<u32>(),
+        ) else {
+            return Err(format!("{}: invalid size or maximum value", filename));
+        };
+        // sampling an empty image would divide by zero
+        if width == 0 || height == 0 {
+            return Err(format!("{}: image is {}x{}", filename, width, height));
+        }
+        if width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .map_or(true, |size| bytes.len() < offset + size)
+        {
+            return Err(format!("{}: truncated pixel data", filename));
+        }
+
+        let pixels = bytes[offset..offset + width * height * 3]
+            .chunks_exact(3)
+            .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .collect();
+
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn write(&self, filename: &str) -> io::Result<()> {
+        let mut bytes = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        for pixel in self.pixels.iter() {
+            bytes.extend_from_slice(pixel);
+        }
+
+        fs::write(filename, bytes)
+    }
+
+    // nearest neighbour lookup as if the image were stretched to width x height
+    pub fn sample(&self, x: usize, y: usize, width: usize, height: usize) -> [u8; 3] {
+        self.pixels[(y * self.height / height) * self.width + x * self.width / width]
+    }
+}
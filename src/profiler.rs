@@ -0,0 +1,217 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::callstack::{frame_name, FrameKind, ShadowStack};
+use crate::disassemble::disassemble8080_op_text;
+
+const REPORT_ADDRESSES: usize = 20;
+const REPORT_PAGES: usize = 16;
+const HISTOGRAM_WIDTH: u64 = 40;
+const REPORT_FUNCTIONS: usize = 30;
+
+pub struct Profiler {
+    opcode_counts: [u64; 256],
+    page_counts: [u64; 256],
+    address_counts: Vec<u64>,
+    address_cycles: Vec<u64>,
+    total_count: u64,
+    total_cycles: u64,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            opcode_counts: [0; 256],
+            page_counts: [0; 256],
+            address_counts: vec![0; 0x10000],
+            address_cycles: vec![0; 0x10000],
+            total_count: 0,
+            total_cycles: 0,
+        }
+    }
+
+    pub fn record(&mut self, program_counter: u16, opcode: u8, cycles: u32) {
+        self.opcode_counts[opcode as usize] += 1;
+        self.page_counts[(program_counter >> 8) as usize] += 1;
+        self.address_counts[program_counter as usize] += 1;
+        self.address_cycles[program_counter as usize] += cycles as u64;
+        self.total_count += 1;
+        self.total_cycles += cycles as u64;
+    }
+
+    fn percent(part: u64, total: u64) -> f64 {
+        if total == 0 {
+            return 0.0;
+        }
+        return part as f64 * 100.0 / total as f64;
+    }
+
+    // hottest addresses and pages by cycles, then a histogram of executed opcodes
+    pub fn print_report(&self, memory: &[u8]) {
+        println!("--------------------------------------------------");
+        println!(
+            "Profile: {} instructions, {} cycles",
+            self.total_count, self.total_cycles
+        );
+
+        let mut addresses: Vec<usize> = (0..0x10000)
+            .filter(|&address| self.address_counts[address] > 0)
+            .collect();
+        addresses.sort_by_key(|&address| std::cmp::Reverse(self.address_cycles[address]));
+
+        println!();
+        println!("addr      count       cycles      %  disassembly");
+        for &address in addresses.iter().take(REPORT_ADDRESSES) {
+            let (text, _) = disassemble8080_op_text(memory, address);
+            println!(
+                "{:04x} {:>10} {:>12} {:>6.2}  {}",
+                address,
+                self.address_counts[address],
+                self.address_cycles[address],
+                Profiler::percent(self.address_cycles[address], self.total_cycles),
+                text
+            );
+        }
+
+        let mut pages: Vec<usize> = (0..256)
+            .filter(|&page| self.page_counts[page] > 0)
+            .collect();
+        pages.sort_by_key(|&page| std::cmp::Reverse(self.page_counts[page]));
+
+        println!();
+        println!("page           count      %");
+        for &page in pages.iter().take(REPORT_PAGES) {
+            println!(
+                "{:02x}00-{:02x}ff {:>10} {:>6.2}",
+                page,
+                page,
+                self.page_counts[page],
+                Profiler::percent(self.page_counts[page], self.total_count)
+            );
+        }
+
+        let mut opcodes: Vec<usize> = (0..256)
+            .filter(|&opcode| self.opcode_counts[opcode] > 0)
+            .collect();
+        opcodes.sort_by_key(|&opcode| std::cmp::Reverse(self.opcode_counts[opcode]));
+        let most = opcodes
+            .first()
+            .map_or(1, |&opcode| self.opcode_counts[opcode]);
+
+        println!();
+        println!("op  mnemonic       count      %");
+        for opcode in opcodes {
+            let (text, _) = disassemble8080_op_text(&[opcode as u8, 0, 0], 0);
+            let count = self.opcode_counts[opcode];
+            println!(
+                "{:02x}  {:<10}{:>10} {:>6.2}  {}",
+                opcode,
+                text.split(['#', '$'])
+                    .next()
+                    .unwrap()
+                    .trim_end_matches([',', ' ']),
+                count,
+                Profiler::percent(count, self.total_count),
+                "#".repeat((count * HISTOGRAM_WIDTH).div_ceil(most) as usize)
+            );
+        }
+        println!("--------------------------------------------------");
+    }
+}
+
+// cycles spent under each shadow call stack path
+pub struct CallProfiler {
+    filename: String,
+    folded: HashMap<Vec<(u16, FrameKind)>, u64>,
+    path: Vec<(u16, FrameKind)>,
+}
+
+impl CallProfiler {
+    pub fn new(filename: &str) -> CallProfiler {
+        CallProfiler {
+            filename: filename.to_string(),
+            folded: HashMap::new(),
+            path: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, stack: &ShadowStack, cycles: u32) {
+        self.path.clear();
+        self.path.extend(
+            stack
+                .frames
+                .iter()
+                .map(|frame| (frame.function, frame.kind)),
+        );
+
+        match self.folded.get_mut(&self.path) {
+            Some(total) => *total += cycles as u64,
+            None => {
+                self.folded.insert(self.path.clone(), cycles as u64);
+            }
+        }
+    }
+
+    fn path_names(path: &[(u16, FrameKind)]) -> Vec<String> {
+        let mut names = vec!["root".to_string()];
+        names.extend(
+            path.iter()
+                .map(|&(function, kind)| frame_name(function, kind)),
+        );
+
+        names
+    }
+
+    // one "root;caller;callee cycles" line per stack, as flamegraph tools expect
+    pub fn write_folded(&self) -> io::Result<()> {
+        let mut lines: Vec<String> = self
+            .folded
+            .iter()
+            .map(|(path, cycles)| {
+                format!("{} {}", CallProfiler::path_names(path).join(";"), cycles)
+            })
+            .collect();
+        lines.sort();
+
+        let mut writer = BufWriter::new(File::create(&self.filename)?);
+        for line in lines {
+            writeln!(writer, "{}", line)?;
+        }
+
+        writer.flush()
+    }
+
+    // inclusive counts a function once per stack even when it recurses
+    pub fn print_report(&self) {
+        let mut inclusive: HashMap<String, u64> = HashMap::new();
+        let mut exclusive: HashMap<String, u64> = HashMap::new();
+        let mut total = 0;
+        for (path, &cycles) in self.folded.iter() {
+            let names = CallProfiler::path_names(path);
+            *exclusive.entry(names.last().unwrap().clone()).or_default() += cycles;
+            for name in names.into_iter().collect::<BTreeSet<_>>() {
+                *inclusive.entry(name).or_default() += cycles;
+            }
+            total += cycles;
+        }
+
+        let mut functions: Vec<(&String, &u64)> = inclusive.iter().collect();
+        functions.sort_by_key(|&(name, cycles)| (std::cmp::Reverse(*cycles), name.clone()));
+
+        println!("--------------------------------------------------");
+        println!("function      inclusive      %     exclusive      %");
+        for (name, &cycles) in functions.into_iter().take(REPORT_FUNCTIONS) {
+            let own = exclusive.get(name).cloned().unwrap_or(0);
+            println!(
+                "{:<10} {:>12} {:>6.2} {:>12} {:>6.2}",
+                name,
+                cycles,
+                Profiler::percent(cycles, total),
+                own,
+                Profiler::percent(own, total)
+            );
+        }
+        println!("--------------------------------------------------");
+    }
+}
@@ -0,0 +1,247 @@
+use std::fs;
+
+use crate::checksum::{crc32, sha1};
+use crate::machine;
+
+// what a known image is and where it runs
+#[derive(Clone, Copy, PartialEq)]
+pub enum RomKind {
+    // one file holding every chip of an arcade set in order, for the named machine
+    Arcade(&'static str),
+    // CP/M program, run in test mode
+    Cpm,
+    // monitor or other bare program, loaded at address 0 and run without CP/M calls
+    Monitor,
+}
+
+// where CP/M loads programs, after the zero page
+pub const CPM_LOAD_ADDRESS: usize = 0x100;
+
+struct KnownRom {
+    description: String,
+    size: usize,
+    crc32: u32,
+    sha1: String,
+    kind: RomKind,
+}
+
+// a chip dump known to be bad, as listed in a ROM database
+struct BadDump {
+    machine: &'static str,
+    chip: String,
+    crc32: u32,
+}
+
+pub struct Identification {
+    pub description: String,
+    pub kind: RomKind,
+    // chips of an arcade set that match a known bad dump
+    pub bad_chips: Vec<&'static str>,
+    // chips of an arcade set that match nothing, modified or undocumented dumps
+    pub unknown_chips: Vec<&'static str>,
+}
+
+// CP/M test programs found by the banner they print, copies of them differ in how
+// the last record is padded so a checksum would miss most
+const BANNERS: [(&str, &[u8]); 4] = [
+    (
+        "8080PRE, 8080 preliminary instruction tests",
+        b"8080 Preliminary tests complete",
+    ),
+    (
+        "8080EXM, 8080 instruction exerciser",
+        b"8080 instruction exerciser",
+    ),
+    ("CPUTEST, SuperSoft Associates CPU test", b"DIAGNOSTICS II"),
+    (
+        "TST8080, Microcosm Associates 8080/8085 CPU diagnostic",
+        b"MICROCOSM ASSOCIATES 8080/8085 CPU DIAGNOSTIC",
+    ),
+];
+
+// images checked against a dump here: size, CRC32, SHA1, kind, description
+const KNOWN_ROMS: [(usize, u32, &str, RomKind, &str); 1] = [(
+    1453,
+    0x298d02dc,
+    "52593a9e6b657940c22eb08b769b06563408057a",
+    RomKind::Cpm,
+    "Microcosm Associates 8080/8085 CPU diagnostic 1.0",
+)];
+
+// chips known to be bad dumps: machine, chip, CRC32
+const BAD_DUMPS: [(&str, &str, u32); 0] = [];
+
+// standalone programs and bad dumps, arcade sets are matched chip by chip against the
+// machine drivers, more of either can be loaded from a file
+pub struct RomDb {
+    roms: Vec<KnownRom>,
+    bad_dumps: Vec<BadDump>,
+}
+
+fn parse_crc(text: &str) -> Result<u32, String> {
+    u32::from_str_radix(text, 16).map_err(|_| format!("invalid CRC32 {}", text))
+}
+
+fn parse_sha1(text: &str) -> Result<String, String> {
+    if text.len() != 40 || !text.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("invalid SHA1 {}", text));
+    }
+
+    Ok(text.to_ascii_lowercase())
+}
+
+impl RomDb {
+    pub fn new() -> RomDb {
+        RomDb {
+            roms: KNOWN_ROMS
+                .iter()
+                .map(|(size, crc32, sha1, kind, description)| KnownRom {
+                    description: description.to_string(),
+                    size: *size,
+                    crc32: *crc32,
+                    sha1: sha1.to_string(),
+                    kind: *kind,
+                })
+                .collect(),
+            bad_dumps: BAD_DUMPS
+                .iter()
+                .map(|(machine, chip, crc32)| BadDump {
+                    machine,
+                    chip: chip.to_string(),
+                    crc32: *crc32,
+                })
+                .collect(),
+        }
+    }
+
+    // one image per line, # starts a comment:
+    //   rom <size> <crc32> <sha1> <cpm|monitor|machine> <description>
+    //   bad <machine> <chip> <crc32>
+    pub fn load(&mut self, filename: &str) -> Result<(), String> {
+        let text = match fs::read_to_string(filename) {
+            Ok(res) => res,
+            Err(why) => return Err(format!("{}: {}", filename, why)),
+        };
+
+        for (ind, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap();
+            if let Err(why) = self.parse_line(line) {
+                return Err(format!("{}:{}: {}", filename, ind + 1, why));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let machine = |name: &str| match machine::find_driver(name) {
+            Some(driver) => Ok(driver.name),
+            None => Err(format!("no machine {}", name)),
+        };
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            [] => (),
+            ["rom", size, crc, sha, kind, ref description @ ..] if !description.is_empty() => {
+                self.roms.push(KnownRom {
+                    description: description.join(" "),
+                    size: size.parse().map_err(|_| format!("invalid size {}", size))?,
+                    crc32: parse_crc(crc)?,
+                    sha1: parse_sha1(sha)?,
+                    kind: match kind {
+                        "cpm" => RomKind::Cpm,
+                        "monitor" => RomKind::Monitor,
+                        name => RomKind::Arcade(machine(name)?),
+                    },
+                })
+            }
+            ["bad", name, chip, crc] => {
+                let machine = machine(name)?;
+                let driver = machine::find_driver(machine).unwrap();
+                if !driver.roms.iter().any(|rom| rom.name == chip) {
+                    return Err(format!("{} has no chip {}", machine, chip));
+                }
+                self.bad_dumps.push(BadDump {
+                    machine,
+                    chip: chip.to_string(),
+                    crc32: parse_crc(crc)?,
+                })
+            }
+            _ => {
+                return Err("expected rom <size> <crc32> <sha1> <kind> <description> \
+                     or bad <machine> <chip> <crc32>"
+                    .to_string())
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_bad_dump(&self, machine: &str, chip: &str, crc: u32) -> bool {
+        self.bad_dumps
+            .iter()
+            .any(|dump| dump.machine == machine && dump.chip == chip && dump.crc32 == crc)
+    }
+
+    pub fn identify(&self, image: &[u8]) -> Option<Identification> {
+        let crc = crc32(image);
+        if let Some(rom) = self
+            .roms
+            .iter()
+            .find(|rom| rom.size == image.len() && rom.crc32 == crc && rom.sha1 == sha1(image))
+        {
+            return Some(Identification {
+                description: rom.description.clone(),
+                kind: rom.kind,
+                bad_chips: Vec::new(),
+                unknown_chips: Vec::new(),
+            });
+        }
+
+        // a set counts as found when any of its chips match a good or bad dump
+        for driver in machine::drivers() {
+            if driver.roms.iter().any(|chip| chip.crc32.is_none())
+                || driver.rom_size() != image.len()
+            {
+                continue;
+            }
+
+            let mut offset = 0;
+            let mut bad_chips = Vec::new();
+            let mut unknown_chips = Vec::new();
+            for chip in driver.roms.iter() {
+                let crc = crc32(&image[offset..offset + chip.size]);
+                if self.is_bad_dump(driver.name, chip.name, crc) {
+                    bad_chips.push(chip.name);
+                } else if Some(crc) != chip.crc32 {
+                    unknown_chips.push(chip.name);
+                }
+                offset += chip.size;
+            }
+            if unknown_chips.len() < driver.roms.len() {
+                return Some(Identification {
+                    description: driver.description.to_string(),
+                    kind: RomKind::Arcade(driver.name),
+                    bad_chips,
+                    unknown_chips,
+                });
+            }
+        }
+
+        // a program too big for the CP/M program area can't be one of these
+        if image.len() <= 0x10000 - CPM_LOAD_ADDRESS {
+            for (description, banner) in BANNERS {
+                if image.windows(banner.len()).any(|window| window == banner) {
+                    return Some(Identification {
+                        description: description.to_string(),
+                        kind: RomKind::Cpm,
+                        bad_chips: Vec::new(),
+                        unknown_chips: Vec::new(),
+                    });
+                }
+            }
+        }
+
+        return None;
+    }
+}
@@ -0,0 +1,139 @@
+use std::fs;
+use std::path::Path;
+
+use crate::checksum::{crc32, sha1};
+use crate::machine::{self, MachineDriver, RomChip};
+use crate::romdb::RomDb;
+use crate::zip::ZipArchive;
+
+// where the chip dumps of a set are read from
+enum RomSource {
+    Directory(String),
+    Zip(ZipArchive),
+}
+
+impl RomSource {
+    fn read(&self, name: &str) -> Result<Option<Vec<u8>>, String> {
+        match self {
+            RomSource::Directory(directory) => {
+                let entries = match fs::read_dir(directory) {
+                    Ok(res) => res,
+                    Err(why) => return Err(format!("{}: {}", directory, why)),
+                };
+                // dumps are often renamed to upper case
+                for entry in entries.flatten() {
+                    if entry
+                        .file_name()
+                        .to_string_lossy()
+                        .eq_ignore_ascii_case(name)
+                    {
+                        return match fs::read(entry.path()) {
+                            Ok(res) => Ok(Some(res)),
+                            Err(why) => Err(format!("{}: {}", entry.path().display(), why)),
+                        };
+                    }
+                }
+                Ok(None)
+            }
+            RomSource::Zip(archive) => archive.read(name),
+        }
+    }
+}
+
+pub fn is_rom_set(path: &str) -> bool {
+    Path::new(path).is_dir() || path.to_ascii_lowercase().ends_with(".zip")
+}
+
+fn open(path: &str) -> Result<RomSource, String> {
+    match Path::new(path).is_dir() {
+        true => Ok(RomSource::Directory(path.to_string())),
+        false => Ok(RomSource::Zip(ZipArchive::open(path)?)),
+    }
+}
+
+// the first machine with every one of its chips in the set, they are checked once it loads
+pub fn find_machine(path: &str) -> Result<Option<MachineDriver>, String> {
+    let source = open(path)?;
+    for driver in machine::drivers() {
+        let mut complete = true;
+        for chip in driver.roms.iter() {
+            if source.read(chip.name)?.is_none() {
+                complete = false;
+                break;
+            }
+        }
+        if complete {
+            return Ok(Some(driver));
+        }
+    }
+
+    return Ok(None);
+}
+
+// checks a dump against the set, listing everything wrong with it
+fn verify(machine: &str, chip: &RomChip, contents: &[u8], rom_db: &RomDb) -> Vec<String> {
+    let mut problems = Vec::new();
+    if contents.len() != chip.size {
+        problems.push(format!(
+            "{}: wrong size, expected {} bytes, got {}",
+            chip.name,
+            chip.size,
+            contents.len()
+        ));
+        return problems;
+    }
+    if let Some(expected) = chip.crc32 {
+        let actual = crc32(contents);
+        if actual != expected {
+            let dump = match rom_db.is_bad_dump(machine, chip.name, actual) {
+                true => "known bad dump",
+                false => "unknown dump",
+            };
+            problems.push(format!(
+                "{}: {}, CRC32 {:08x}, expected {:08x}",
+                chip.name, dump, actual, expected
+            ));
+        }
+    }
+    if let Some(expected) = chip.sha1 {
+        let actual = sha1(contents);
+        if actual != expected {
+            problems.push(format!(
+                "{}: unknown dump, SHA1 {}, expected {}",
+                chip.name, actual, expected
+            ));
+        }
+    }
+
+    return problems;
+}
+
+// every chip of the set from a directory or zip archive, one after another in the order the
+// machine lists them, the same image as the chips dumped into a single file
+pub fn load_rom_set(
+    path: &str,
+    machine: &MachineDriver,
+    rom_db: &RomDb,
+) -> Result<Vec<u8>, String> {
+    let source = open(path)?;
+
+    let mut image = Vec::new();
+    let mut problems = Vec::new();
+    for chip in machine.roms.iter() {
+        match source.read(chip.name)? {
+            Some(contents) => {
+                let chip_problems = verify(machine.name, chip, &contents, rom_db);
+                if chip_problems.is_empty() {
+                    image.extend_from_slice(&contents);
+                }
+                problems.extend(chip_problems);
+            }
+            None => problems.push(format!("{}: missing", chip.name)),
+        }
+    }
+    if !problems.is_empty() {
+        return Err(problems.join("\n"));
+    }
+
+    return Ok(image);
+}
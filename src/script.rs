@@ -0,0 +1,279 @@
+use std::fs;
+
+use crate::i8080::State;
+
+// frames a press holds its control, then as many released so presses in a row count separately
+const PRESS_FRAMES: u64 = 5;
+
+#[derive(Clone, Copy)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+// mem[address] compared with a byte
+struct Condition {
+    text: String,
+    address: u16,
+    comparison: Comparison,
+    value: u8,
+}
+
+enum Command {
+    Set(String, bool),
+    Wait(u64),
+    WaitUntil(Condition),
+    Expect(Condition),
+    Quit,
+}
+
+// automated play, run a step at the start of every frame:
+//   wait <n> [frames]            do nothing for n frames
+//   wait until mem[addr]==value  wait for memory, also !=, <, <=, > and >=
+//   press <control> [frames]     hold and then release, 5 frames by default
+//   hold <control> [frames]      hold for frames, or until released
+//   release <control>
+//   expect mem[addr]==value      report whether memory holds the value
+//   quit                         stop emulation
+// commands are separated by ; or new lines, # starts a comment
+pub struct Script {
+    name: String,
+    // line and command
+    commands: Vec<(usize, Command)>,
+    position: usize,
+    // frames left of the current wait
+    waiting: u64,
+    failures: usize,
+}
+
+fn parse_number(text: &str) -> Result<u64, String> {
+    let result = match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+
+    result.map_err(|_| format!("invalid number {}", text))
+}
+
+fn parse_condition(text: &str) -> Result<Condition, String> {
+    let invalid = || format!("expected mem[address]==value, got {}", text);
+    let compact: String = text.split_whitespace().collect();
+    let Some((address, rest)) = compact
+        .strip_prefix("mem[")
+        .and_then(|rest| rest.split_once(']'))
+    else {
+        return Err(invalid());
+    };
+    let (comparison, value) = [
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<=", Comparison::LessEqual),
+        (">=", Comparison::GreaterEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+    ]
+    .iter()
+    .find_map(|(operator, comparison)| Some((*comparison, rest.strip_prefix(operator)?)))
+    .ok_or_else(invalid)?;
+
+    let address = parse_number(address)?;
+    let value = parse_number(value)?;
+    if address > 0xffff {
+        return Err(format!(
+            "address {:x} is outside the 64K address space",
+            address
+        ));
+    }
+    if value > 0xff {
+        return Err(format!("{} doesn't fit in a byte", value));
+    }
+
+    Ok(Condition {
+        text: compact.clone(),
+        address: address as u16,
+        comparison,
+        value: value as u8,
+    })
+}
+
+fn parse_control(words: &[&str], controls: &[&str]) -> Result<String, String> {
+    match words.get(1) {
+        Some(control) if controls.contains(control) => Ok(control.to_string()),
+        Some(control) => Err(format!(
+            "no control {}, expected one of {}",
+            control,
+            controls.join(", ")
+        )),
+        None => Err(format!("{} needs a control", words[0])),
+    }
+}
+
+// the frame count after a control, or the default when there is none
+fn parse_frames(words: &[&str], default: Option<u64>) -> Result<Option<u64>, String> {
+    match words.get(2) {
+        Some(frames) => Ok(Some(parse_number(frames)?)),
+        None => Ok(default),
+    }
+}
+
+fn parse_command(command: &str, controls: &[&str]) -> Result<Vec<Command>, String> {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    if matches!(words[0], "press" | "hold" | "release") && words.len() > 3 {
+        return Err(format!(
+            "unexpected {} after {}",
+            words[3..].join(" "),
+            words[0]
+        ));
+    }
+
+    let commands = match words[0] {
+        "wait" if words.get(1) == Some(&"until") => {
+            let condition = command.split_once("until").unwrap().1;
+            vec![Command::WaitUntil(parse_condition(condition)?)]
+        }
+        "wait" => match words[1..] {
+            [frames] | [frames, "frames" | "frame"] => vec![Command::Wait(parse_number(frames)?)],
+            _ => return Err("expected wait <frames> or wait until <condition>".to_string()),
+        },
+        "press" => {
+            let control = parse_control(&words, controls)?;
+            let frames = parse_frames(&words, Some(PRESS_FRAMES))?.unwrap();
+            vec![
+                Command::Set(control.clone(), true),
+                Command::Wait(frames),
+                Command::Set(control, false),
+                Command::Wait(frames),
+            ]
+        }
+        "hold" => {
+            let control = parse_control(&words, controls)?;
+            match parse_frames(&words, None)? {
+                Some(frames) => vec![
+                    Command::Set(control.clone(), true),
+                    Command::Wait(frames),
+                    Command::Set(control, false),
+                ],
+                None => vec![Command::Set(control, true)],
+            }
+        }
+        "release" => vec![Command::Set(parse_control(&words, controls)?, false)],
+        "expect" => {
+            let condition = command.split_once("expect").unwrap().1;
+            vec![Command::Expect(parse_condition(condition)?)]
+        }
+        "quit" => vec![Command::Quit],
+        word => return Err(format!("unknown command {}", word)),
+    };
+
+    Ok(commands)
+}
+
+impl Condition {
+    fn holds(&self, actual: u8) -> bool {
+        match self.comparison {
+            Comparison::Equal => actual == self.value,
+            Comparison::NotEqual => actual != self.value,
+            Comparison::Less => actual < self.value,
+            Comparison::LessEqual => actual <= self.value,
+            Comparison::Greater => actual > self.value,
+            Comparison::GreaterEqual => actual >= self.value,
+        }
+    }
+}
+
+impl Script {
+    // controls are the names the machine gives its inputs
+    pub fn parse(name: &str, text: &str, controls: &[&str]) -> Result<Script, String> {
+        let mut commands = Vec::new();
+        for (ind, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap();
+            for command in line.split(';').filter(|command| !command.trim().is_empty()) {
+                match parse_command(command, controls) {
+                    Ok(res) => commands.extend(res.into_iter().map(|command| (ind + 1, command))),
+                    Err(why) => return Err(format!("{}:{}: {}", name, ind + 1, why)),
+                }
+            }
+        }
+
+        Ok(Script {
+            name: name.to_string(),
+            commands,
+            position: 0,
+            waiting: 0,
+            failures: 0,
+        })
+    }
+
+    pub fn load(filename: &str, controls: &[&str]) -> Result<Script, String> {
+        match fs::read_to_string(filename) {
+            Ok(res) => Script::parse(filename, &res, controls),
+            Err(why) => Err(format!("{}: {}", filename, why)),
+        }
+    }
+
+    // run commands until one waits for a later frame
+    pub fn run_frame(&mut self, state: &mut State) {
+        loop {
+            if self.waiting > 0 {
+                self.waiting -= 1;
+                return;
+            }
+            let Some((line, command)) = self.commands.get(self.position) else {
+                return;
+            };
+            self.position += 1;
+
+            match command {
+                Command::Set(control, pressed) => {
+                    // checked against the machine's controls when parsed
+                    state.input_map.set_control(control, *pressed).unwrap();
+                }
+                Command::Wait(frames) => self.waiting = *frames,
+                Command::WaitUntil(condition) => {
+                    if !condition.holds(state.read_memory(condition.address)) {
+                        self.position -= 1;
+                        return;
+                    }
+                }
+                Command::Expect(condition) => {
+                    let actual = state.read_memory(condition.address);
+                    if condition.holds(actual) {
+                        println!("{}:{}: passed {}", self.name, line, condition.text);
+                    } else {
+                        println!(
+                            "{}:{}: FAILED {}, was {:#04x} on frame {}",
+                            self.name, line, condition.text, actual, state.video.frames
+                        );
+                        self.failures += 1;
+                    }
+                }
+                Command::Quit => {
+                    state.should_exit = true;
+                    return;
+                }
+            }
+        }
+    }
+
+    // says where an unfinished script stopped and how many expectations failed
+    pub fn report(&self) {
+        if let Some((line, _)) = self.commands.get(self.position) {
+            println!("{}:{}: script stopped before finishing", self.name, line);
+        }
+        if self.failures > 0 {
+            println!("{}: {} expectations failed", self.name, self.failures);
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.position == self.commands.len() && self.waiting == 0
+    }
+
+    pub fn passed(&self) -> bool {
+        self.failures == 0
+    }
+}
@@ -0,0 +1,114 @@
+// MB14241 barrel shifter used by the Midway 8080 boards to move sprites horizontally,
+// State::fork copies it with the rest of the machine and snapshots save it as bytes
+pub const SHIFT_REGISTER_SIZE: usize = 3;
+
+#[derive(Clone, Copy)]
+pub struct ShiftRegister {
+    pub value: u16,
+    pub offset: u8,
+}
+
+impl ShiftRegister {
+    pub fn new() -> ShiftRegister {
+        ShiftRegister {
+            value: 0,
+            offset: 0,
+        }
+    }
+
+    // OUT 2, only the low 3 bits are wired
+    pub fn set_offset(&mut self, data: u8) {
+        self.offset = data & 0b111;
+    }
+
+    // OUT 4, new data enters the high byte and the old high byte moves down
+    pub fn shift_in(&mut self, data: u8) {
+        self.value = ((data as u16) << 8) | (self.value >> 8);
+    }
+
+    // IN 3, 8 bits starting offset bits below the top
+    pub fn read(&self) -> u8 {
+        ((self.value << self.offset) >> 8) as u8
+    }
+
+    // little endian value, then the offset
+    pub fn to_bytes(&self) -> [u8; SHIFT_REGISTER_SIZE] {
+        let value = self.value.to_le_bytes();
+        [value[0], value[1], self.offset]
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> ShiftRegister {
+        ShiftRegister {
+            value: u16::from_le_bytes([bytes[0], bytes[1]]),
+            offset: bytes[2] & 0b111,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ShiftRegister, SHIFT_REGISTER_SIZE};
+
+    // OUT 4 each byte, OUT 2 the offset, then IN 3
+    fn shifted(bytes: &[u8], offset: u8) -> u8 {
+        let mut shift_register = ShiftRegister::new();
+        for byte in bytes {
+            shift_register.shift_in(*byte);
+        }
+        shift_register.set_offset(offset);
+
+        return shift_register.read();
+    }
+
+    #[test]
+    fn reads_from_the_offset() {
+        assert_eq!(shifted(&[0xaa, 0x55], 0), 0x55);
+        assert_eq!(shifted(&[0xaa, 0x55], 3), 0xad);
+        assert_eq!(shifted(&[0xaa, 0x55], 7), 0xd5);
+    }
+
+    #[test]
+    fn keeps_the_last_two_bytes() {
+        assert_eq!(shifted(&[0xff, 0x0f, 0xf0], 4), 0x00);
+        assert_eq!(shifted(&[0x12, 0x34, 0x56], 0), 0x56);
+        assert_eq!(shifted(&[0x12, 0x34, 0x56], 4), 0x63);
+    }
+
+    #[test]
+    fn offset_uses_the_low_three_bits() {
+        assert_eq!(
+            shifted(&[0x80, 0x01], 0b11111001),
+            shifted(&[0x80, 0x01], 1)
+        );
+        assert_eq!(shifted(&[0x80, 0x01], 1), 0x03);
+    }
+
+    #[test]
+    fn offset_stays_between_shifts() {
+        let mut shift_register = ShiftRegister::new();
+        shift_register.set_offset(2);
+        shift_register.shift_in(0x00);
+        shift_register.shift_in(0xc0);
+        assert_eq!(shift_register.read(), 0x00);
+        shift_register.shift_in(0x01);
+        assert_eq!(shift_register.read(), 0x07);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut shift_register = ShiftRegister::new();
+        shift_register.shift_in(0x12);
+        shift_register.shift_in(0x34);
+        shift_register.set_offset(5);
+        let bytes = shift_register.to_bytes();
+        assert_eq!(bytes, [0x12, 0x34, 5]);
+
+        let restored = ShiftRegister::from_bytes(&bytes);
+        assert_eq!(restored.value, shift_register.value);
+        assert_eq!(restored.read(), shift_register.read());
+        assert_eq!(
+            ShiftRegister::from_bytes(&[0; SHIFT_REGISTER_SIZE]).read(),
+            0
+        );
+    }
+}
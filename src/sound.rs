@@ -0,0 +1,134 @@
+use crate::mixer::SampleMixer;
+use crate::synth::SoundSynth;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SoundEvent {
+    UfoStart,
+    UfoStop,
+    Shot,
+    PlayerDeath,
+    InvaderHit,
+    ExtendedPlay,
+    AmplifierOn,
+    AmplifierOff,
+    // fleet movement tones 1-4
+    Fleet(u8),
+    UfoHit,
+}
+
+impl SoundEvent {
+    // numbered sample files (0.wav, 1.wav, ...) as shipped in the common sample sets
+    pub fn sample_number(&self) -> Option<usize> {
+        match self {
+            SoundEvent::UfoStart => Some(0),
+            SoundEvent::Shot => Some(1),
+            SoundEvent::PlayerDeath => Some(2),
+            SoundEvent::InvaderHit => Some(3),
+            SoundEvent::Fleet(tone) => Some(3 + *tone as usize),
+            SoundEvent::UfoHit => Some(8),
+            SoundEvent::ExtendedPlay => Some(9),
+            SoundEvent::UfoStop | SoundEvent::AmplifierOn | SoundEvent::AmplifierOff => None,
+        }
+    }
+}
+
+// last values written to the sound ports, sounds trigger on rising edges
+#[derive(Clone)]
+pub struct SoundLatches {
+    pub port3: u8,
+    pub port5: u8,
+}
+
+impl SoundLatches {
+    pub fn new() -> SoundLatches {
+        SoundLatches { port3: 0, port5: 0 }
+    }
+
+    fn rising(old: u8, new: u8, bit: u8) -> bool {
+        (old & bit) == 0 && (new & bit) != 0
+    }
+
+    fn falling(old: u8, new: u8, bit: u8) -> bool {
+        (old & bit) != 0 && (new & bit) == 0
+    }
+
+    // OUT 3
+    pub fn write_port3(&mut self, value: u8) -> Vec<SoundEvent> {
+        let old = self.port3;
+        self.port3 = value;
+
+        let mut events = Vec::new();
+        if SoundLatches::rising(old, value, 0b00100000) {
+            events.push(SoundEvent::AmplifierOn);
+        }
+        if SoundLatches::falling(old, value, 0b00100000) {
+            events.push(SoundEvent::AmplifierOff);
+        }
+        if SoundLatches::rising(old, value, 0b00000001) {
+            events.push(SoundEvent::UfoStart);
+        }
+        if SoundLatches::falling(old, value, 0b00000001) {
+            events.push(SoundEvent::UfoStop);
+        }
+        for (bit, event) in [
+            (0b00000010, SoundEvent::Shot),
+            (0b00000100, SoundEvent::PlayerDeath),
+            (0b00001000, SoundEvent::InvaderHit),
+            (0b00010000, SoundEvent::ExtendedPlay),
+        ] {
+            if SoundLatches::rising(old, value, bit) {
+                events.push(event);
+            }
+        }
+
+        return events;
+    }
+
+    // OUT 5
+    pub fn write_port5(&mut self, value: u8) -> Vec<SoundEvent> {
+        let old = self.port5;
+        self.port5 = value;
+
+        let mut events = Vec::new();
+        for tone in 1..=4 {
+            if SoundLatches::rising(old, value, 1 << (tone - 1)) {
+                events.push(SoundEvent::Fleet(tone));
+            }
+        }
+        if SoundLatches::rising(old, value, 0b00010000) {
+            events.push(SoundEvent::UfoHit);
+        }
+
+        return events;
+    }
+}
+
+// the sound hardware behind ports 3 and 5, either recorded samples or a model of its circuits
+pub enum SoundBoard {
+    Samples(SampleMixer),
+    Synth(SoundSynth),
+}
+
+impl SoundBoard {
+    pub fn trigger(&mut self, event: SoundEvent, cycle: u64) {
+        match self {
+            SoundBoard::Samples(mixer) => mixer.trigger(event, cycle),
+            SoundBoard::Synth(synth) => synth.trigger(event, cycle),
+        }
+    }
+
+    pub fn advance(&mut self, cycle: u64) {
+        match self {
+            SoundBoard::Samples(mixer) => mixer.advance(cycle),
+            SoundBoard::Synth(synth) => synth.advance(cycle),
+        }
+    }
+
+    // PCM rendered since the last call
+    pub fn take_output(&mut self) -> Vec<i16> {
+        match self {
+            SoundBoard::Samples(mixer) => std::mem::take(&mut mixer.output),
+            SoundBoard::Synth(synth) => std::mem::take(&mut synth.output),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use crate::mixer::SAMPLE_RATE;
+use crate::sound::SoundEvent;
+
+const VOLUME: f64 = 0.25 * i16::MAX as f64;
+
+const KILO: f64 = 1e3;
+const MICRO: f64 = 1e-6;
+
+// the sound board's timing parts, every frequency, sweep and decay below is computed from
+// these: (resistor, capacitor) for RC networks and SN76477 oscillators, (R1, R2, C) for 555s
+
+// SN76477 driving the saucer, its super low frequency oscillator sweeps the VCO
+const UFO_SLF: (f64, f64) = (120.0 * KILO, 1.0 * MICRO);
+const UFO_VCO: (f64, f64) = (8.2 * KILO, 0.1 * MICRO);
+// clock of the 17 bit noise shift register feeding the explosions
+const NOISE_CLOCK: f64 = 7515.0;
+// explosions, noise through a low pass and a capacitor discharging after the trigger
+const SHOT_FILTER: (f64, f64) = (10.0 * KILO, 0.01 * MICRO);
+const SHOT_ENVELOPE: (f64, f64) = (100.0 * KILO, 1.0 * MICRO);
+const DEATH_FILTER: (f64, f64) = (10.0 * KILO, 0.1 * MICRO);
+const DEATH_ENVELOPE: (f64, f64) = (470.0 * KILO, 1.0 * MICRO);
+const HIT_FILTER: (f64, f64) = (10.0 * KILO, 0.047 * MICRO);
+const HIT_ENVELOPE: (f64, f64) = (100.0 * KILO, 1.0 * MICRO);
+// fleet march 555, each of the four port 5 bits switches in its own R2
+const FLEET_R1: f64 = 10.0 * KILO;
+const FLEET_R2: [f64; 4] = [68.0 * KILO, 82.0 * KILO, 91.0 * KILO, 100.0 * KILO];
+const FLEET_C: f64 = 0.1 * MICRO;
+const FLEET_ENVELOPE: (f64, f64) = (82.0 * KILO, 1.0 * MICRO);
+// saucer hit, a tone 555 gated by a slow one
+const UFO_HIT_TONE: (f64, f64, f64) = (10.0 * KILO, 47.0 * KILO, 0.022 * MICRO);
+const UFO_HIT_GATE: (f64, f64, f64) = (10.0 * KILO, 56.0 * KILO, 1.0 * MICRO);
+const UFO_HIT_ENVELOPE: (f64, f64) = (330.0 * KILO, 1.0 * MICRO);
+// extra life chime, the same arrangement
+const BONUS_TONE: (f64, f64, f64) = (10.0 * KILO, 27.0 * KILO, 0.022 * MICRO);
+const BONUS_GATE: (f64, f64, f64) = (10.0 * KILO, 82.0 * KILO, 1.0 * MICRO);
+const BONUS_ENVELOPE: (f64, f64) = (100.0 * KILO, 2.2 * MICRO);
+
+// 555 astable: frequency 1.44 / ((R1 + 2 R2) C), high for (R1 + R2) / (R1 + 2 R2) of a cycle
+fn astable((r1, r2, c): (f64, f64, f64)) -> (f64, f64) {
+    return (1.44 / ((r1 + 2.0 * r2) * c), (r1 + r2) / (r1 + 2.0 * r2));
+}
+
+// SN76477 oscillators run at 0.64 / RC
+fn sn76477_frequency((r, c): (f64, f64)) -> f64 {
+    return 0.64 / (r * c);
+}
+
+fn time_constant((r, c): (f64, f64)) -> f64 {
+    return r * c;
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SynthSound {
+    Shot,
+    PlayerDeath,
+    InvaderHit,
+    ExtendedPlay,
+    Fleet(u8),
+    UfoHit,
+}
+
+struct SynthVoice {
+    sound: SynthSound,
+    age: f64,
+    phase: f64,
+    filtered: f64,
+}
+
+impl SynthSound {
+    // the envelope capacitor's RC
+    fn envelope(&self) -> (f64, f64) {
+        match self {
+            SynthSound::Shot => SHOT_ENVELOPE,
+            SynthSound::PlayerDeath => DEATH_ENVELOPE,
+            SynthSound::InvaderHit => HIT_ENVELOPE,
+            SynthSound::ExtendedPlay => BONUS_ENVELOPE,
+            SynthSound::Fleet(_) => FLEET_ENVELOPE,
+            SynthSound::UfoHit => UFO_HIT_ENVELOPE,
+        }
+    }
+
+    // three time constants, the envelope is down to 5%
+    fn length(&self) -> f64 {
+        return 3.0 * time_constant(self.envelope());
+    }
+}
+
+// the Space Invaders sound board's outputs modelled from its timing components, used when
+// no samples are available: oscillators run at the frequencies their resistors and
+// capacitors set, explosions are clocked noise through RC low passes, and every one-shot
+// fades as its envelope capacitor discharges
+pub struct SoundSynth {
+    ufo_playing: bool,
+    ufo_phase: f64,
+    ufo_slf_phase: f64,
+    // 17 bit noise shift register shared by the explosions, and its clock's phase
+    noise: u32,
+    noise_phase: f64,
+    voices: Vec<SynthVoice>,
+    muted: bool,
+    cycles_per_sample: f64,
+    // samples rendered since start, output is drained by the audio sink
+    rendered: usize,
+    pub output: Vec<i16>,
+}
+
+impl SoundSynth {
+    pub fn new(cpu_clock: f64) -> SoundSynth {
+        SoundSynth {
+            ufo_playing: false,
+            ufo_phase: 0.0,
+            ufo_slf_phase: 0.0,
+            noise: 1,
+            noise_phase: 0.0,
+            voices: Vec::new(),
+            muted: true,
+            cycles_per_sample: cpu_clock / SAMPLE_RATE as f64,
+            rendered: 0,
+            output: Vec::new(),
+        }
+    }
+
+    pub fn trigger(&mut self, event: SoundEvent, cycle: u64) {
+        self.advance(cycle);
+
+        let sound = match event {
+            SoundEvent::AmplifierOn | SoundEvent::AmplifierOff => {
+                self.muted = event == SoundEvent::AmplifierOff;
+                return;
+            }
+            SoundEvent::UfoStart | SoundEvent::UfoStop => {
+                self.ufo_playing = event == SoundEvent::UfoStart;
+                return;
+            }
+            SoundEvent::Shot => SynthSound::Shot,
+            SoundEvent::PlayerDeath => SynthSound::PlayerDeath,
+            SoundEvent::InvaderHit => SynthSound::InvaderHit,
+            SoundEvent::ExtendedPlay => SynthSound::ExtendedPlay,
+            SoundEvent::Fleet(tone) => SynthSound::Fleet(tone),
+            SoundEvent::UfoHit => SynthSound::UfoHit,
+        };
+
+        // retriggering restarts a sound rather than layering it
+        self.voices.retain(|voice| voice.sound != sound);
+        self.voices.push(SynthVoice {
+            sound,
+            age: 0.0,
+            phase: 0.0,
+            filtered: 0.0,
+        });
+    }
+
+    // the shift register steps on each clock edge and holds its output between them
+    fn next_noise(&mut self, dt: f64) -> f64 {
+        self.noise_phase += NOISE_CLOCK * dt;
+        while self.noise_phase >= 1.0 {
+            self.noise_phase -= 1.0;
+            let bit = (self.noise ^ (self.noise >> 3)) & 1;
+            self.noise = (self.noise >> 1) | (bit << 16);
+        }
+        if self.noise & 1 != 0 {
+            1.0
+        } else {
+            -1.0
+        }
+    }
+
+    // the SLF's triangle sweeps the VCO across its 10:1 range
+    fn ufo_sample(&mut self, dt: f64) -> f64 {
+        self.ufo_slf_phase = (self.ufo_slf_phase + sn76477_frequency(UFO_SLF) * dt).fract();
+        let sweep = 1.0 - (2.0 * self.ufo_slf_phase - 1.0).abs();
+        let frequency = sn76477_frequency(UFO_VCO) * (0.1 + 0.9 * sweep);
+        self.ufo_phase = (self.ufo_phase + frequency * dt).fract();
+
+        return if self.ufo_phase < 0.5 { 1.0 } else { -1.0 };
+    }
+
+    fn voice_sample(voice: &mut SynthVoice, noise: f64, dt: f64) -> f64 {
+        let envelope = (-voice.age / time_constant(voice.sound.envelope())).exp();
+        // 555 output at a time, high for its duty cycle
+        let pulse = |phase: f64, duty: f64| if phase < duty { 1.0 } else { -1.0 };
+        // one pole RC low pass over the noise
+        let mut filter = |rc: (f64, f64)| {
+            voice.filtered += (noise - voice.filtered) * dt / (time_constant(rc) + dt);
+            voice.filtered
+        };
+
+        let value = match voice.sound {
+            SynthSound::Shot => filter(SHOT_FILTER),
+            SynthSound::PlayerDeath => filter(DEATH_FILTER),
+            SynthSound::InvaderHit => filter(HIT_FILTER),
+            SynthSound::Fleet(tone) => {
+                let (frequency, duty) = astable((FLEET_R1, FLEET_R2[tone as usize - 1], FLEET_C));
+                voice.phase = (voice.phase + frequency * dt).fract();
+                pulse(voice.phase, duty)
+            }
+            SynthSound::UfoHit | SynthSound::ExtendedPlay => {
+                let (tone, gate) = match voice.sound {
+                    SynthSound::UfoHit => (UFO_HIT_TONE, UFO_HIT_GATE),
+                    _ => (BONUS_TONE, BONUS_GATE),
+                };
+                let (frequency, duty) = astable(tone);
+                let (gate_frequency, gate_duty) = astable(gate);
+                voice.phase = (voice.phase + frequency * dt).fract();
+                let open = (voice.age * gate_frequency).fract() < gate_duty;
+                if open {
+                    pulse(voice.phase, duty)
+                } else {
+                    0.0
+                }
+            }
+        };
+        voice.age += dt;
+
+        return value * envelope;
+    }
+
+    // render output up to the given CPU cycle
+    pub fn advance(&mut self, cycle: u64) {
+        let dt = 1.0 / SAMPLE_RATE as f64;
+        let target = (cycle as f64 / self.cycles_per_sample) as usize;
+        while self.rendered < target {
+            let noise = self.next_noise(dt);
+            let mut mixed = 0.0;
+            if self.ufo_playing {
+                mixed += self.ufo_sample(dt);
+            }
+            for voice in self.voices.iter_mut() {
+                mixed += SoundSynth::voice_sample(voice, noise, dt);
+            }
+            self.voices.retain(|voice| voice.age < voice.sound.length());
+
+            if self.muted {
+                mixed = 0.0;
+            }
+            self.output.push(
+                (mixed * VOLUME)
+                    .round()
+                    .clamp(i16::MIN as f64, i16::MAX as f64) as i16,
+            );
+            self.rendered += 1;
+        }
+    }
+}
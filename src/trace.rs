@@ -0,0 +1,319 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+
+use crate::disassemble::disassemble8080_op_text;
+use crate::i8080::State;
+
+const BINARY_MAGIC: &[u8; 8] = b"I8080TR\x02";
+const BINARY_RECORD_SIZE: usize = 24;
+
+pub enum TraceFormat {
+    Text,
+    Binary,
+}
+
+// machine state just before an instruction executes
+pub struct TraceRecord {
+    pub cycles: u64,
+    pub program_counter: u16,
+    pub stack_pointer: u16,
+    // the four bytes from PC on, as reference traces show them
+    pub op_bytes: [u8; 4],
+    // A F B C D E H L
+    pub registers: [u8; 8],
+}
+
+impl TraceRecord {
+    pub fn capture(state: &State) -> TraceRecord {
+        let mut op_bytes = [0; 4];
+        for (ind, byte) in op_bytes.iter_mut().enumerate() {
+            *byte = state.read_memory(state.program_counter.wrapping_add(ind as u16));
+        }
+
+        TraceRecord {
+            cycles: state.cycle_count,
+            program_counter: state.program_counter,
+            stack_pointer: state.stack_pointer,
+            op_bytes,
+            registers: [
+                state.reg_a,
+                state.flags_to_u8(),
+                state.reg_b,
+                state.reg_c,
+                state.reg_d,
+                state.reg_e,
+                state.reg_h,
+                state.reg_l,
+            ],
+        }
+    }
+
+    // instruction text and length
+    pub fn disassemble(&self) -> (String, usize) {
+        disassemble8080_op_text(&self.op_bytes, 0)
+    }
+
+    // F as SZ-A-P-C, a letter for each set flag, . for clear and - for the fixed bits
+    pub fn flags_text(&self) -> String {
+        "SZ-A-P-C"
+            .chars()
+            .enumerate()
+            .map(|(ind, name)| match name {
+                '-' => '-',
+                _ if self.registers[1] & (0x80 >> ind) != 0 => name,
+                _ => '.',
+            })
+            .collect()
+    }
+
+    // the layout superzazu/8080 logs with i8080_debug_output followed by the flags and
+    // the instruction, cut -f1-2 leaves lines that diff against its traces:
+    // PC: 0100, AF: 0002, BC: 0000, DE: 0000, HL: 0000, SP: 0000, CYC: 0\t(C3 AB 01 00)\t..-.-.-.\tJMP    $01ab
+    pub fn to_text(&self) -> String {
+        let r = &self.registers;
+        let pair = |high: u8, low: u8| ((high as u16) << 8) | low as u16;
+
+        format!(
+            "PC: {:04X}, AF: {:04X}, BC: {:04X}, DE: {:04X}, HL: {:04X}, SP: {:04X}, CYC: {}\t({:02X} {:02X} {:02X} {:02X})\t{}\t{}",
+            self.program_counter,
+            pair(r[0], r[1]),
+            pair(r[2], r[3]),
+            pair(r[4], r[5]),
+            pair(r[6], r[7]),
+            self.stack_pointer,
+            self.cycles,
+            self.op_bytes[0],
+            self.op_bytes[1],
+            self.op_bytes[2],
+            self.op_bytes[3],
+            self.flags_text(),
+            self.disassemble().0
+        )
+    }
+
+    // takes lines with or without the trailing flags and instruction
+    pub fn from_text(line: &str) -> Option<TraceRecord> {
+        let (fields, bytes) = line.split_once('(')?;
+        let mut values = [0u64; 7];
+        for (value, (name, field)) in values.iter_mut().zip(
+            ["PC", "AF", "BC", "DE", "HL", "SP", "CYC"]
+                .iter()
+                .zip(fields.split(',')),
+        ) {
+            let (found, text) = field.split_once(':')?;
+            if found.trim() != *name {
+                return None;
+            }
+            *value = match *name {
+                "CYC" => text.trim().parse().ok()?,
+                _ => u16::from_str_radix(text.trim(), 16).ok()? as u64,
+            };
+        }
+
+        let mut op_bytes = [0; 4];
+        let (bytes, _) = bytes.split_once(')')?;
+        let mut tokens = bytes.split_whitespace();
+        for byte in op_bytes.iter_mut() {
+            *byte = u8::from_str_radix(tokens.next()?, 16).ok()?;
+        }
+
+        let mut registers = [0; 8];
+        for (ind, pair) in values[1..5].iter().enumerate() {
+            registers[ind * 2] = (pair >> 8) as u8;
+            registers[ind * 2 + 1] = *pair as u8;
+        }
+
+        Some(TraceRecord {
+            cycles: values[6],
+            program_counter: values[0] as u16,
+            stack_pointer: values[5] as u16,
+            op_bytes,
+            registers,
+        })
+    }
+
+    // little endian: cycles, PC, SP, bytes from PC, registers
+    pub fn to_bytes(&self) -> [u8; BINARY_RECORD_SIZE] {
+        let mut bytes = [0; BINARY_RECORD_SIZE];
+        bytes[0..8].copy_from_slice(&self.cycles.to_le_bytes());
+        bytes[8..10].copy_from_slice(&self.program_counter.to_le_bytes());
+        bytes[10..12].copy_from_slice(&self.stack_pointer.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.op_bytes);
+        bytes[16..24].copy_from_slice(&self.registers);
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> TraceRecord {
+        TraceRecord {
+            cycles: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            program_counter: u16::from_le_bytes(bytes[8..10].try_into().unwrap()),
+            stack_pointer: u16::from_le_bytes(bytes[10..12].try_into().unwrap()),
+            op_bytes: bytes[12..16].try_into().unwrap(),
+            registers: bytes[16..24].try_into().unwrap(),
+        }
+    }
+}
+
+pub struct Tracer {
+    writer: BufWriter<File>,
+    format: TraceFormat,
+    // inclusive bounds
+    pub address_range: (u16, u16),
+    pub cycle_window: (u64, u64),
+}
+
+impl Tracer {
+    pub fn new(filename: &str, format: TraceFormat) -> io::Result<Tracer> {
+        let mut writer = BufWriter::new(File::create(filename)?);
+        if matches!(format, TraceFormat::Binary) {
+            writer.write_all(BINARY_MAGIC)?;
+        }
+
+        Ok(Tracer {
+            writer,
+            format,
+            address_range: (0x0000, 0xffff),
+            cycle_window: (0, u64::MAX),
+        })
+    }
+
+    // write the state of the instruction about to execute if it passes the filters
+    pub fn record(&mut self, state: &State) {
+        let pc = state.program_counter;
+        let cycles = state.cycle_count;
+        if pc < self.address_range.0
+            || pc > self.address_range.1
+            || cycles < self.cycle_window.0
+            || cycles > self.cycle_window.1
+        {
+            return;
+        }
+
+        let record = TraceRecord::capture(state);
+        let result = match self.format {
+            TraceFormat::Text => writeln!(self.writer, "{}", record.to_text()),
+            TraceFormat::Binary => self.writer.write_all(&record.to_bytes()),
+        };
+        if let Err(why) = result {
+            panic!("Failed to write trace: {}", why);
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Err(why) = self.writer.flush() {
+            panic!("Failed to write trace: {}", why);
+        }
+    }
+}
+
+// parses "start-end" in hex
+pub fn parse_address_range(range: &str) -> (u16, u16) {
+    let parsed = range.split_once('-').and_then(|(start, end)| {
+        Some((
+            u16::from_str_radix(start.trim_start_matches("0x"), 16).ok()?,
+            u16::from_str_radix(end.trim_start_matches("0x"), 16).ok()?,
+        ))
+    });
+    match parsed {
+        Some(range) => range,
+        None => panic!(
+            "Invalid address range {} (expected start-end in hex)",
+            range
+        ),
+    }
+}
+
+// parses "start-end" in decimal, end may be omitted
+pub fn parse_cycle_window(window: &str) -> (u64, u64) {
+    let parsed = window.split_once('-').and_then(|(start, end)| {
+        let end = match end {
+            "" => u64::MAX,
+            _ => end.parse().ok()?,
+        };
+        Some((start.parse().ok()?, end))
+    });
+    match parsed {
+        Some(window) => window,
+        None => panic!("Invalid cycle window {} (expected start-end)", window),
+    }
+}
+
+fn read_trace(filename: &str) -> Vec<TraceRecord> {
+    let contents = match fs::read(filename) {
+        Ok(res) => res,
+        Err(why) => panic!("Failed to open file {}: {}", filename, why),
+    };
+
+    if contents.starts_with(BINARY_MAGIC) {
+        contents[BINARY_MAGIC.len()..]
+            .chunks_exact(BINARY_RECORD_SIZE)
+            .map(TraceRecord::from_bytes)
+            .collect()
+    } else {
+        String::from_utf8_lossy(&contents)
+            .lines()
+            .filter_map(TraceRecord::from_text)
+            .collect()
+    }
+}
+
+// print the first record at which two traces disagree
+pub fn find_divergence(first_filename: &str, second_filename: &str) {
+    let first = read_trace(first_filename);
+    let second = read_trace(second_filename);
+
+    for (ind, (a, b)) in first.iter().zip(second.iter()).enumerate() {
+        let mut fields = Vec::new();
+        if a.cycles != b.cycles {
+            fields.push("CYCLES".to_string());
+        }
+        if a.program_counter != b.program_counter {
+            fields.push("PC".to_string());
+        }
+        let length = a.disassemble().1.max(b.disassemble().1);
+        if a.op_bytes[..length] != b.op_bytes[..length] {
+            fields.push("BYTES".to_string());
+        }
+        for (name, (x, y)) in ["A", "F", "B", "C", "D", "E", "H", "L"]
+            .iter()
+            .zip(a.registers.iter().zip(b.registers.iter()))
+        {
+            if x != y {
+                fields.push(name.to_string());
+            }
+        }
+        if a.stack_pointer != b.stack_pointer {
+            fields.push("SP".to_string());
+        }
+        // bytes after the instruction may differ without changing what runs
+        if fields.is_empty() {
+            continue;
+        }
+
+        println!("Traces diverge at record {}: {}", ind, fields.join(" "));
+        if ind > 0 {
+            let previous = &first[ind - 1];
+            println!("  previous  {}", previous.to_text());
+        }
+        println!("  {:<9} {}", "first", a.to_text());
+        println!("  {:<9} {}", "second", b.to_text());
+        return;
+    }
+
+    if first.len() != second.len() {
+        println!(
+            "Traces match for {} records, then {} ends ({} vs {} records)",
+            first.len().min(second.len()),
+            if first.len() < second.len() {
+                first_filename
+            } else {
+                second_filename
+            },
+            first.len(),
+            second.len()
+        );
+    } else {
+        println!("Traces match ({} records)", first.len());
+    }
+}
@@ -0,0 +1,84 @@
+// 9.984 MHz pixel clock, the CPU runs at a fifth of it
+pub const PIXEL_CLOCK: f64 = 9_984_000.0;
+// 320 pixels per line including blanking
+pub const CYCLES_PER_LINE: u64 = 64;
+pub const LINES_PER_FRAME: u64 = 262;
+pub const VISIBLE_LINES: usize = 224;
+pub const BYTES_PER_LINE: usize = 32;
+// lines sharing a row of color cells
+const LINES_PER_CELL: usize = 8;
+// RST 1 and RST 2
+const MID_SCREEN_LINE: u64 = 96;
+const END_OF_SCREEN_LINE: u64 = 224;
+
+// beam position derived from the cycle count, copying video RAM a line at a time
+#[derive(Clone)]
+pub struct Video {
+    video_ram: usize,
+    // color cells over the first line, for boards with color RAM
+    color_ram: Option<usize>,
+    frame_start: u64,
+    // next line the beam will draw
+    line: u64,
+    pub frames: u64,
+    // video RAM as the beam saw it, 32 bytes per line
+    pub frame: Vec<u8>,
+    // color byte over each byte of the frame, captured with it
+    pub colors: Option<Vec<u8>>,
+}
+
+impl Video {
+    pub fn new(video_ram: usize, color_ram: Option<usize>) -> Video {
+        Video {
+            video_ram,
+            color_ram,
+            frame_start: 0,
+            line: 0,
+            frames: 0,
+            frame: vec![0; VISIBLE_LINES * BYTES_PER_LINE],
+            colors: color_ram.map(|_| vec![0; VISIBLE_LINES * BYTES_PER_LINE]),
+        }
+    }
+
+    // start a new frame from the given cycle
+    pub fn reset(&mut self, cycle_count: u64) {
+        self.frame_start = cycle_count;
+        self.line = 0;
+    }
+
+    pub fn scanline(&self, cycle_count: u64) -> u64 {
+        ((cycle_count - self.frame_start) / CYCLES_PER_LINE).min(LINES_PER_FRAME - 1)
+    }
+
+    // move the beam up to the cycle, returning the RST code of any interrupt line it passed
+    pub fn advance(&mut self, cycle_count: u64, memory: &[u8]) -> Option<u8> {
+        let mut interrupt = None;
+        while self.frame_start + (self.line + 1) * CYCLES_PER_LINE <= cycle_count {
+            let line = self.line as usize;
+            if line < VISIBLE_LINES {
+                let start = self.video_ram + line * BYTES_PER_LINE;
+                self.frame[line * BYTES_PER_LINE..(line + 1) * BYTES_PER_LINE]
+                    .copy_from_slice(&memory[start..start + BYTES_PER_LINE]);
+                if let (Some(color_ram), Some(colors)) = (self.color_ram, &mut self.colors) {
+                    let start = color_ram + line / LINES_PER_CELL * BYTES_PER_LINE;
+                    colors[line * BYTES_PER_LINE..(line + 1) * BYTES_PER_LINE]
+                        .copy_from_slice(&memory[start..start + BYTES_PER_LINE]);
+                }
+            }
+
+            self.line += 1;
+            match self.line {
+                MID_SCREEN_LINE => interrupt = Some(1),
+                END_OF_SCREEN_LINE => interrupt = Some(2),
+                LINES_PER_FRAME => {
+                    self.frame_start += LINES_PER_FRAME * CYCLES_PER_LINE;
+                    self.line = 0;
+                    self.frames += 1;
+                }
+                _ => (),
+            }
+        }
+
+        return interrupt;
+    }
+}
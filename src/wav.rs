@@ -0,0 +1,108 @@
+use std::fs;
+use std::io;
+
+// mono 16-bit PCM audio
+pub struct Wave {
+    pub sample_rate: u32,
+    pub samples: Vec<i16>,
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+impl Wave {
+    // reads 8 or 16-bit PCM, mixing any channels down to mono
+    pub fn read(filename: &str) -> Result<Wave, String> {
+        let bytes = match fs::read(filename) {
+            Ok(res) => res,
+            Err(why) => return Err(format!("{}: {}", filename, why)),
+        };
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            return Err(format!("{}: not a WAV file", filename));
+        }
+
+        let mut format = None;
+        let mut offset = 12;
+        while offset + 8 <= bytes.len() {
+            let id = &bytes[offset..offset + 4];
+            let size = read_u32(&bytes, offset + 4) as usize;
+            let body = offset + 8;
+            let end = (body + size).min(bytes.len());
+
+            if id == b"fmt " && size >= 16 {
+                if body + 16 > bytes.len() {
+                    return Err(format!("{}: truncated fmt chunk", filename));
+                }
+                let audio_format = read_u16(&bytes, body);
+                let channels = read_u16(&bytes, body + 2) as usize;
+                let sample_rate = read_u32(&bytes, body + 4);
+                let bits = read_u16(&bytes, body + 14);
+                if audio_format != 1 || channels == 0 || (bits != 8 && bits != 16) {
+                    return Err(format!("{}: only 8 or 16-bit PCM is supported", filename));
+                }
+                format = Some((channels, sample_rate, bits));
+            } else if id == b"data" {
+                let Some((channels, sample_rate, bits)) = format else {
+                    return Err(format!("{}: data before fmt chunk", filename));
+                };
+                let width = bits as usize / 8;
+                let samples = bytes[body..end]
+                    .chunks_exact(width * channels)
+                    .map(|frame| {
+                        let total: i32 = frame
+                            .chunks_exact(width)
+                            .map(|sample| match width {
+                                1 => ((sample[0] as i32) - 128) << 8,
+                                _ => i16::from_le_bytes([sample[0], sample[1]]) as i32,
+                            })
+                            .sum();
+                        (total / channels as i32) as i16
+                    })
+                    .collect();
+
+                return Ok(Wave {
+                    sample_rate,
+                    samples,
+                });
+            }
+
+            // chunks are padded to an even size
+            offset = body + size + (size & 1);
+        }
+
+        Err(format!("{}: no data chunk", filename))
+    }
+
+    pub fn write(&self, filename: &str) -> io::Result<()> {
+        let data_size = (self.samples.len() * 2) as u32;
+        let mut bytes = Vec::with_capacity(44 + data_size as usize);
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_size).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        // PCM, mono
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&self.sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&(self.sample_rate * 2).to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_size.to_le_bytes());
+        for sample in self.samples.iter() {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+
+        fs::write(filename, bytes)
+    }
+}
@@ -0,0 +1,428 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::disassemble::disassemble8080_op_text;
+
+// how control leaves an instruction
+#[derive(Clone, Copy)]
+pub enum Flow {
+    Next,
+    Jump(u16),
+    Branch(u16),
+    Call(u16),
+    CallIf(u16),
+    Return,
+    ReturnIf,
+    Indirect,
+}
+
+pub struct Instruction {
+    pub address: u16,
+    pub length: usize,
+    pub opcode: u8,
+    pub operand: u16,
+    pub text: String,
+    pub flow: Flow,
+    // found by following execution rather than the linear sweep
+    pub reached: bool,
+}
+
+// every way an address can be referenced
+#[derive(Default)]
+pub struct XrefEntry {
+    pub jumped_from: BTreeSet<u16>,
+    pub called_from: BTreeSet<u16>,
+    pub read_by: BTreeSet<u16>,
+    pub written_by: BTreeSet<u16>,
+    pub loaded_by: BTreeSet<u16>,
+}
+
+pub struct BasicBlock {
+    pub start: u16,
+    pub instructions: Vec<u16>,
+    pub successors: Vec<u16>,
+}
+
+pub struct Routine {
+    pub entry: u16,
+    pub blocks: BTreeMap<u16, BasicBlock>,
+    pub calls: BTreeSet<u16>,
+    pub in_ports: BTreeSet<u8>,
+    pub out_ports: BTreeSet<u8>,
+}
+
+pub struct Analysis {
+    pub instructions: BTreeMap<u16, Instruction>,
+    pub xrefs: BTreeMap<u16, XrefEntry>,
+    pub routines: BTreeMap<u16, Routine>,
+}
+
+// decode how control flows out of the instruction at address
+fn decode_flow(code_buffer: &[u8], address: usize) -> Flow {
+    let word = ((code_buffer[address + 2] as u16) << 8) | (code_buffer[address + 1] as u16);
+    match code_buffer[address] {
+        0xc3 => Flow::Jump(word),
+        0xc2 | 0xca | 0xd2 | 0xda | 0xe2 | 0xea | 0xf2 | 0xfa => Flow::Branch(word),
+        0xcd => Flow::Call(word),
+        0xc4 | 0xcc | 0xd4 | 0xdc | 0xe4 | 0xec | 0xf4 | 0xfc => Flow::CallIf(word),
+        0xc7 | 0xcf | 0xd7 | 0xdf | 0xe7 | 0xef | 0xf7 | 0xff => {
+            Flow::Call((code_buffer[address] & 0x38) as u16)
+        }
+        0xc9 => Flow::Return,
+        0xc0 | 0xc8 | 0xd0 | 0xd8 | 0xe0 | 0xe8 | 0xf0 | 0xf8 => Flow::ReturnIf,
+        0xe9 => Flow::Indirect,
+        _ => Flow::Next,
+    }
+}
+
+fn decode_instruction(memory: &[u8], address: u16, reached: bool) -> Instruction {
+    let (text, length) = disassemble8080_op_text(memory, address as usize);
+    Instruction {
+        address,
+        length,
+        opcode: memory[address as usize],
+        operand: ((memory[address as usize + 2] as u16) << 8)
+            | (memory[address as usize + 1] as u16),
+        text,
+        flow: decode_flow(memory, address as usize),
+        reached,
+    }
+}
+
+// addresses execution continues at within the same routine
+fn local_successors(instruction: &Instruction) -> Vec<u16> {
+    let next = instruction.address.wrapping_add(instruction.length as u16);
+    match instruction.flow {
+        Flow::Next | Flow::Call(_) | Flow::CallIf(_) | Flow::ReturnIf => vec![next],
+        Flow::Jump(to) => vec![to],
+        Flow::Branch(to) => vec![to, next],
+        Flow::Return | Flow::Indirect => vec![],
+    }
+}
+
+fn ends_block(instruction: &Instruction) -> bool {
+    matches!(
+        instruction.flow,
+        Flow::Jump(_) | Flow::Branch(_) | Flow::Return | Flow::ReturnIf | Flow::Indirect
+    )
+}
+
+impl Analysis {
+    // recursive descent from the reset and RST vectors through every reachable instruction,
+    // then a linear sweep of the bytes it never reached so code only referenced as data,
+    // like jump tables, and dead code still get decoded and cross-referenced
+    pub fn new(code_buffer: &[u8]) -> Analysis {
+        let code_end = code_buffer.len();
+        let mut memory = code_buffer.to_vec();
+        memory.resize(0x10000 + 2, 0);
+
+        let mut analysis = Analysis {
+            instructions: BTreeMap::new(),
+            xrefs: BTreeMap::new(),
+            routines: BTreeMap::new(),
+        };
+
+        let mut entries: BTreeSet<u16> = (0..8).map(|code| code * 8).collect();
+        let mut pending: Vec<u16> = entries.iter().rev().cloned().collect();
+
+        while let Some(address) = pending.pop() {
+            if (address as usize) >= code_end || analysis.instructions.contains_key(&address) {
+                continue;
+            }
+
+            let instruction = decode_instruction(&memory, address, true);
+            analysis.record_references(&instruction);
+
+            if let Flow::Call(to) | Flow::CallIf(to) = instruction.flow {
+                entries.insert(to);
+                pending.push(to);
+            }
+            pending.extend(local_successors(&instruction));
+
+            analysis.instructions.insert(address, instruction);
+        }
+
+        let mut address = 0;
+        while address < code_end {
+            if let Some(instruction) = analysis.instructions.get(&(address as u16)) {
+                address += instruction.length;
+                continue;
+            }
+            let instruction = decode_instruction(&memory, address as u16, false);
+            analysis.record_references(&instruction);
+            address += instruction.length;
+            analysis
+                .instructions
+                .insert(instruction.address, instruction);
+        }
+
+        for entry in entries {
+            if analysis.instructions.contains_key(&entry) {
+                let routine = analysis.build_routine(entry);
+                analysis.routines.insert(entry, routine);
+            }
+        }
+
+        analysis
+    }
+
+    fn record_references(&mut self, instruction: &Instruction) {
+        let address = instruction.address;
+        let word = instruction.operand;
+
+        match instruction.flow {
+            Flow::Jump(to) | Flow::Branch(to) => {
+                self.xrefs
+                    .entry(to)
+                    .or_default()
+                    .jumped_from
+                    .insert(address);
+            }
+            Flow::Call(to) | Flow::CallIf(to) => {
+                self.xrefs
+                    .entry(to)
+                    .or_default()
+                    .called_from
+                    .insert(address);
+            }
+            _ => (),
+        }
+
+        match instruction.opcode {
+            // LDA, LHLD
+            0x3a => {
+                self.xrefs.entry(word).or_default().read_by.insert(address);
+            }
+            0x2a => {
+                self.xrefs.entry(word).or_default().read_by.insert(address);
+                let high = word.wrapping_add(1);
+                self.xrefs.entry(high).or_default().read_by.insert(address);
+            }
+            // STA, SHLD
+            0x32 => {
+                self.xrefs
+                    .entry(word)
+                    .or_default()
+                    .written_by
+                    .insert(address);
+            }
+            0x22 => {
+                self.xrefs
+                    .entry(word)
+                    .or_default()
+                    .written_by
+                    .insert(address);
+                let high = word.wrapping_add(1);
+                self.xrefs
+                    .entry(high)
+                    .or_default()
+                    .written_by
+                    .insert(address);
+            }
+            // LXI B/D/H/SP
+            0x01 | 0x11 | 0x21 | 0x31 => {
+                self.xrefs
+                    .entry(word)
+                    .or_default()
+                    .loaded_by
+                    .insert(address);
+            }
+            _ => (),
+        }
+    }
+
+    // walk a routine without following calls, splitting it into basic blocks
+    fn build_routine(&self, entry: u16) -> Routine {
+        let mut routine = Routine {
+            entry,
+            blocks: BTreeMap::new(),
+            calls: BTreeSet::new(),
+            in_ports: BTreeSet::new(),
+            out_ports: BTreeSet::new(),
+        };
+
+        let mut members = BTreeSet::new();
+        let mut leaders = BTreeSet::from([entry]);
+        let mut pending = vec![entry];
+        while let Some(address) = pending.pop() {
+            let Some(instruction) = self.instructions.get(&address) else {
+                continue;
+            };
+            if !members.insert(address) {
+                continue;
+            }
+
+            match instruction.flow {
+                Flow::Call(to) | Flow::CallIf(to) => {
+                    routine.calls.insert(to);
+                }
+                _ => (),
+            }
+            match instruction.opcode {
+                0xdb => {
+                    routine.in_ports.insert(instruction.operand as u8);
+                }
+                0xd3 => {
+                    routine.out_ports.insert(instruction.operand as u8);
+                }
+                _ => (),
+            }
+
+            let successors = local_successors(instruction);
+            if ends_block(instruction) {
+                leaders.extend(successors.iter().cloned());
+            }
+            pending.extend(successors);
+        }
+
+        for &leader in leaders.iter() {
+            if !members.contains(&leader) {
+                continue;
+            }
+
+            let mut block = BasicBlock {
+                start: leader,
+                instructions: Vec::new(),
+                successors: Vec::new(),
+            };
+            let mut address = leader;
+            loop {
+                let instruction = &self.instructions[&address];
+                block.instructions.push(address);
+
+                let successors = local_successors(instruction);
+                if ends_block(instruction) {
+                    block.successors = successors
+                        .into_iter()
+                        .filter(|to| members.contains(to))
+                        .collect();
+                    break;
+                }
+
+                let next = successors[0];
+                if leaders.contains(&next) || !members.contains(&next) {
+                    if members.contains(&next) {
+                        block.successors.push(next);
+                    }
+                    break;
+                }
+                address = next;
+            }
+            routine.blocks.insert(leader, block);
+        }
+
+        routine
+    }
+
+    // print every referenced address followed by the ports each routine uses,
+    // code the sweep found but execution never reaches is marked unreached
+    pub fn print_xref_table(&self) {
+        for (address, entry) in self.xrefs.iter() {
+            let unreached = match self.instructions.get(address) {
+                Some(instruction) if !instruction.reached => "  unreached",
+                _ => "",
+            };
+            match self.routines.get(address) {
+                Some(_) => println!("{:04x}  sub_{:04x}{}", address, address, unreached),
+                None => println!("{:04x}{}", address, unreached),
+            }
+            let kinds = [
+                ("called from", &entry.called_from),
+                ("jumped from", &entry.jumped_from),
+                ("read by", &entry.read_by),
+                ("written by", &entry.written_by),
+                ("loaded by", &entry.loaded_by),
+            ];
+            for (name, sources) in kinds {
+                if sources.is_empty() {
+                    continue;
+                }
+                let mut line = format!("      {:<12}", name);
+                for source in sources {
+                    write!(line, " {:04x}", source).unwrap();
+                }
+                println!("{}", line);
+            }
+        }
+
+        println!();
+        println!("routine    IN ports         OUT ports");
+        for routine in self.routines.values() {
+            if routine.in_ports.is_empty() && routine.out_ports.is_empty() {
+                continue;
+            }
+            let ports = |ports: &BTreeSet<u8>| {
+                ports
+                    .iter()
+                    .map(|port| format!("{:02x}", port))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+            println!(
+                "sub_{:04x}   {:<16} {}",
+                routine.entry,
+                ports(&routine.in_ports),
+                ports(&routine.out_ports)
+            );
+        }
+    }
+
+    // Graphviz call graph with one node per routine
+    pub fn call_graph_dot(&self) -> String {
+        let mut dot = String::from("digraph callgraph {\n    node [shape=box];\n");
+        for routine in self.routines.values() {
+            writeln!(dot, "    \"sub_{:04x}\";", routine.entry).unwrap();
+            for callee in routine.calls.iter() {
+                writeln!(
+                    dot,
+                    "    \"sub_{:04x}\" -> \"sub_{:04x}\";",
+                    routine.entry, callee
+                )
+                .unwrap();
+            }
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+
+    // Graphviz control-flow graph of a single routine, one node per basic block
+    pub fn control_flow_dot(&self, routine: &Routine) -> String {
+        let mut dot = format!(
+            "digraph sub_{:04x} {{\n    node [shape=box, fontname=\"monospace\"];\n",
+            routine.entry
+        );
+        for block in routine.blocks.values() {
+            let mut label = String::new();
+            for address in block.instructions.iter() {
+                let text = &self.instructions[address].text;
+                write!(label, "{:04x} {}\\l", address, text.replace('"', "\\\"")).unwrap();
+            }
+            writeln!(dot, "    \"{:04x}\" [label=\"{}\"];", block.start, label).unwrap();
+            for successor in block.successors.iter() {
+                writeln!(dot, "    \"{:04x}\" -> \"{:04x}\";", block.start, successor).unwrap();
+            }
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+
+    pub fn write_call_graph(&self, filename: &str) -> io::Result<()> {
+        fs::write(filename, self.call_graph_dot())
+    }
+
+    // writes sub_XXXX.dot for every routine into directory
+    pub fn write_control_flow_graphs(&self, directory: &str) -> io::Result<()> {
+        fs::create_dir_all(directory)?;
+        for routine in self.routines.values() {
+            let path = Path::new(directory).join(format!("sub_{:04x}.dot", routine.entry));
+            fs::write(path, self.control_flow_dot(routine))?;
+        }
+
+        Ok(())
+    }
+}
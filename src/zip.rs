@@ -0,0 +1,126 @@
+use std::fs;
+
+use crate::checksum::crc32;
+use crate::inflate::inflate;
+
+const END_OF_DIRECTORY: u32 = 0x06054b50;
+const DIRECTORY_ENTRY: u32 = 0x02014b50;
+const LOCAL_HEADER: u32 = 0x04034b50;
+
+struct Entry {
+    name: String,
+    method: u16,
+    crc32: u32,
+    compressed_size: usize,
+    size: usize,
+    header_offset: usize,
+}
+
+// read only zip archive, stored and deflated entries
+pub struct ZipArchive {
+    filename: String,
+    data: Vec<u8>,
+    entries: Vec<Entry>,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+impl ZipArchive {
+    pub fn open(filename: &str) -> Result<ZipArchive, String> {
+        let data = match fs::read(filename) {
+            Ok(res) => res,
+            Err(why) => return Err(format!("{}: {}", filename, why)),
+        };
+        let corrupt = || format!("{}: not a zip archive or corrupt", filename);
+
+        // the directory is found from its trailer, which may be followed by a comment
+        let Some(end) = (0..data.len().saturating_sub(21))
+            .rev()
+            .find(|offset| read_u32(&data, *offset) == Some(END_OF_DIRECTORY))
+        else {
+            return Err(corrupt());
+        };
+        let count = read_u16(&data, end + 10).ok_or_else(corrupt)?;
+        let mut offset = read_u32(&data, end + 16).ok_or_else(corrupt)? as usize;
+
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            if read_u32(&data, offset) != Some(DIRECTORY_ENTRY) {
+                return Err(corrupt());
+            }
+            let name_length = read_u16(&data, offset + 28).ok_or_else(corrupt)? as usize;
+            let extra_length = read_u16(&data, offset + 30).ok_or_else(corrupt)? as usize;
+            let comment_length = read_u16(&data, offset + 32).ok_or_else(corrupt)? as usize;
+            let name = data
+                .get(offset + 46..offset + 46 + name_length)
+                .ok_or_else(corrupt)?;
+            entries.push(Entry {
+                name: String::from_utf8_lossy(name).to_string(),
+                method: read_u16(&data, offset + 10).ok_or_else(corrupt)?,
+                crc32: read_u32(&data, offset + 16).ok_or_else(corrupt)?,
+                compressed_size: read_u32(&data, offset + 20).ok_or_else(corrupt)? as usize,
+                size: read_u32(&data, offset + 24).ok_or_else(corrupt)? as usize,
+                header_offset: read_u32(&data, offset + 42).ok_or_else(corrupt)? as usize,
+            });
+            offset += 46 + name_length + extra_length + comment_length;
+        }
+
+        Ok(ZipArchive {
+            filename: filename.to_string(),
+            data,
+            entries,
+        })
+    }
+
+    // contents of the file with this name in any folder of the archive, ignoring case
+    pub fn read(&self, name: &str) -> Result<Option<Vec<u8>>, String> {
+        let Some(entry) = self.entries.iter().find(|entry| {
+            let base_name = entry.name.rsplit('/').next().unwrap();
+            base_name.eq_ignore_ascii_case(name)
+        }) else {
+            return Ok(None);
+        };
+        let corrupt = || format!("{}: {} is corrupt", self.filename, entry.name);
+
+        let offset = entry.header_offset;
+        if read_u32(&self.data, offset) != Some(LOCAL_HEADER) {
+            return Err(corrupt());
+        }
+        let name_length = read_u16(&self.data, offset + 26).ok_or_else(corrupt)? as usize;
+        let extra_length = read_u16(&self.data, offset + 28).ok_or_else(corrupt)? as usize;
+        let start = offset + 30 + name_length + extra_length;
+        let compressed = self
+            .data
+            .get(start..start + entry.compressed_size)
+            .ok_or_else(corrupt)?;
+
+        let contents = match entry.method {
+            0 => compressed.to_vec(),
+            8 => match inflate(compressed) {
+                Ok(res) => res,
+                Err(why) => return Err(format!("{}: {}: {}", self.filename, entry.name, why)),
+            },
+            method => {
+                return Err(format!(
+                    "{}: {} uses unsupported compression method {}",
+                    self.filename, entry.name, method
+                ))
+            }
+        };
+        if contents.len() != entry.size || crc32(&contents) != entry.crc32 {
+            return Err(corrupt());
+        }
+
+        Ok(Some(contents))
+    }
+}